@@ -1,99 +1,583 @@
 use crate::errors::*;
-use crate::game::Game;
+use crate::game::{Game, Os, SavePath};
+use crate::steam::Steam;
+use crate::variables::Variables;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::path::{Path, PathBuf};
 
-const VERSION: usize = 1;
+const VERSION: usize = 2;
 
-#[derive(Deserialize, Debug, Serialize)]
+/// The steps to upgrade each version of the database to the next, starting
+/// with version 1.
+const MIGRATIONS: [fn(&mut Value); VERSION - 1] = [migrate_v1];
+
+/// The database compiled in to saveli, which is the first layer.
+const BUNDLED: &str = include_str!("../res/database.json");
+
+/// The games are merged from layers, each overriding the entries of the
+/// previous ones:
+///
+/// 1. The bundled database.
+/// 2. The files in the storage path's `community` directory, in the order of
+///    their names, e.g. an imported manifest.
+/// 3. The user's `user.json` in the storage path, holding custom games.
+///
+/// Custom games take precedence over all others. Only the user's layer is
+/// written by `save`, so new versions of the bundled database are picked up.
+#[derive(Debug, Default)]
 pub struct Database {
-    version: usize,
+    /// Games with the saves which can be used on this machine.
     pub games: Vec<Game>,
-    #[serde(skip)]
+    /// The saves which can't be used on this machine, kept so that they
+    /// aren't lost when saving.
+    unavailable: Vec<Game>,
+    /// The user's layer.
     path: PathBuf,
+    /// The version the user's layer was written with.
+    version: usize,
+    community_path: PathBuf,
+    steam: Option<Steam>,
+    wine_prefixes: Vec<PathBuf>,
 }
 
-impl Database {
-    pub fn new<T: AsRef<Path>>(storage_path: T) -> Result<Database> {
-        let mut db: Database;
+/// Changes to make to a game with `Database::edit`.
+#[derive(Debug, Default)]
+pub struct Edit {
+    pub title: Option<String>,
+    /// Saves to add, or to change the path of if the game already has a save
+    /// with the same id.
+    pub saves: Vec<SavePath>,
+    /// The ids of saves to remove, including those for other operating
+    /// systems.
+    pub removed_saves: Vec<String>,
+}
 
-        let windows_path = storage_path.as_ref().join("windows.json");
-        if windows_path.exists() {
-            db = Database::load_from(&windows_path)?;
-        } else {
-            db = Database::load(include_str!("../res/windows.json"))?;
-            db.path = windows_path;
-            db.save()?;
+#[derive(Deserialize, Serialize)]
+struct DatabaseFile {
+    /// The version the file was written with. The games are always migrated
+    /// to the current version.
+    version: usize,
+    games: Vec<Game>,
+}
+
+impl DatabaseFile {
+    fn parse<T: AsRef<str>>(data: T) -> Result<DatabaseFile> {
+        let mut value: Value = serde_json::from_str(data.as_ref())?;
+        let version = value
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or("The database doesn't specify its version")? as usize;
+
+        if version > VERSION {
+            bail!(ErrorKind::DatabaseTooNew(version, VERSION));
         }
 
-        Ok(db)
-    }
+        for migration in &MIGRATIONS[version.max(1) - 1..] {
+            migration(&mut value);
+        }
 
-    pub fn save(&self) -> Result<()> {
-        println!("Saving {}", self.path.display());
-        let f = std::fs::File::create(&self.path)?;
-        serde_json::to_writer_pretty(f, self)?;
-        Ok(())
+        let mut file: DatabaseFile = serde_json::from_value(value)?;
+        file.version = version;
+        Ok(file)
     }
 
-    fn load_from<T: AsRef<Path>>(path: T) -> Result<Database> {
+    fn read<T: AsRef<Path>>(path: T) -> Result<DatabaseFile> {
         let data = std::fs::read_to_string(&path)?;
-        let mut db = Database::load(data)?;
-        db.path = path.as_ref().to_path_buf();
-        println!(
+        let file = DatabaseFile::parse(data)?;
+        debug!(
             "Loaded {} game entries from {}",
-            db.games.len(),
+            file.games.len(),
             path.as_ref().display()
         );
+        Ok(file)
+    }
+
+    fn write<T: AsRef<Path>>(games: Vec<Game>, path: T) -> Result<()> {
+        debug!("Saving {}", path.as_ref().display());
+        let file = DatabaseFile {
+            version: VERSION,
+            games,
+        };
+        let f = std::fs::File::create(&path)?;
+        serde_json::to_writer_pretty(f, &file)?;
+        Ok(())
+    }
+}
+
+impl Database {
+    pub fn new<T: AsRef<Path>>(storage_path: T, wine_prefixes: &[PathBuf]) -> Result<Database> {
+        let storage_path = storage_path.as_ref();
+        let path = storage_path.join("user.json");
+        let community_path = storage_path.join("community");
+        Database::migrate(storage_path, &path, &community_path)?;
+
+        let mut layers = vec![DatabaseFile::parse(BUNDLED)?.games];
+        for file in Database::community_files(&community_path)? {
+            layers.push(DatabaseFile::read(&file)?.games);
+        }
+
+        let mut version = VERSION;
+        if path.exists() {
+            let file = DatabaseFile::read(&path)?;
+            version = file.version;
+            let mut games = file.games;
+            games.iter_mut().for_each(|g| g.custom = true);
+            layers.push(games);
+        }
+
+        let mut db = Database::load_with(layers, wine_prefixes);
+        db.path = path;
+        db.version = version;
+        db.community_path = community_path;
         Ok(db)
     }
 
-    fn load<T: AsRef<str>>(data: T) -> Result<Database> {
-        let mut db: Database = serde_json::from_str(data.as_ref())?;
+    /// The database files in the storage path which are merged with the bundled
+    /// one, in the order they're merged.
+    pub fn files<T: AsRef<Path>>(storage_path: T) -> Result<Vec<PathBuf>> {
+        let storage_path = storage_path.as_ref();
+        let mut files = Database::community_files(&storage_path.join("community"))?;
 
-        if db.version > VERSION {
-            bail!(ErrorKind::DatabaseTooNew(db.version, VERSION));
+        let path = storage_path.join("user.json");
+        if path.exists() {
+            files.push(path);
         }
 
-        // The sorting of Game prioratises customisations.
-        db.games.sort();
-        db.games.dedup();
+        Ok(files)
+    }
+
+    fn community_files(community_path: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        if !community_path.is_dir() {
+            return Ok(files);
+        }
+
+        for entry in std::fs::read_dir(community_path)? {
+            let file = entry?.path();
+            if file.extension().is_some_and(|e| e == "json") {
+                files.push(file);
+            }
+        }
+        files.sort();
+
+        Ok(files)
+    }
+
+    /// Splits the database written by older versions, which was a copy of the
+    /// bundled one, in to the user's layer and a community file containing
+    /// the entries which aren't bundled any more.
+    fn migrate(storage_path: &Path, path: &Path, community_path: &Path) -> Result<()> {
+        if path.exists() {
+            return Ok(());
+        }
+
+        // The bundled database was Windows only before saves could specify
+        // which operating systems they apply to. Custom games were added on
+        // whichever one the user was running.
+        let db_path = storage_path.join("database.json");
+        let windows_path = storage_path.join("windows.json");
+        let (old_path, mut file) = if db_path.exists() {
+            let file = DatabaseFile::read(&db_path)?;
+            (db_path, file)
+        } else if windows_path.exists() {
+            let mut file = DatabaseFile::read(&windows_path)?;
+            for game in &mut file.games {
+                let os = if game.custom { Os::current() } else { Os::Windows };
+                for save in game.saves.iter_mut().filter(|s| s.os.is_empty()) {
+                    save.os.push(os);
+                }
+            }
+            (windows_path, file)
+        } else {
+            return Ok(());
+        };
+
+        let bundled = DatabaseFile::parse(BUNDLED)?.games;
+        file.games.retain(|g| g.custom || !bundled.contains(g));
+        let (custom, community): (Vec<Game>, Vec<Game>) =
+            file.games.into_iter().partition(|g| g.custom);
+
+        if !community.is_empty() {
+            std::fs::create_dir_all(community_path)?;
+            DatabaseFile::write(community, community_path.join("migrated.json"))?;
+        }
+        DatabaseFile::write(custom, path)?;
+        std::fs::rename(&old_path, backup_path(&old_path, file.version))?;
+
+        Ok(())
+    }
 
-        // Convert path variables to expanded paths
-        for game in &mut db.games {
-            for save in &mut game.saves {
-                save.update_path()?;
+    /// Writes the custom games to the user's layer.
+    pub fn save(&self) -> Result<()> {
+        let mut games: Vec<Game> = self.games.iter().filter(|g| g.custom).cloned().collect();
+        for other in self.unavailable.iter().filter(|g| g.custom) {
+            match games.iter_mut().find(|g| *g == other) {
+                Some(g) => g.saves.extend(other.saves.iter().cloned()),
+                None => games.push(other.clone()),
             }
         }
+        games.sort();
+
+        // Keep the original in case the user wants to go back to an older
+        // version of saveli.
+        let backup = backup_path(&self.path, self.version);
+        if self.version < VERSION && self.path.exists() && !backup.exists() {
+            info!("Backing up {} to {}", self.path.display(), backup.display());
+            std::fs::copy(&self.path, &backup)?;
+        }
 
+        DatabaseFile::write(games, &self.path)
+    }
+
+    #[cfg(test)]
+    fn load<T: AsRef<str>>(data: T) -> Result<Database> {
+        let file = DatabaseFile::parse(data)?;
+        let mut db = Database::load_with(vec![file.games], &[]);
+        db.version = file.version;
         Ok(db)
     }
 
-    pub fn search(&self, keyword: &str) {
+    /// Merges the layers, with the later ones overriding the earlier ones.
+    fn load_with(layers: Vec<Vec<Game>>, wine_prefixes: &[PathBuf]) -> Database {
+        let mut games: Vec<Game> = Vec::new();
+        for layer in layers {
+            for game in layer {
+                if !game.custom {
+                    games.retain(|g| g.custom || *g != game);
+                }
+                games.push(game);
+            }
+        }
+
+        // The sorting of Game prioratises customisations.
+        games.sort();
+        games.dedup();
+
+        let mut db = Database {
+            steam: Steam::locate(),
+            wine_prefixes: wine_prefixes.to_vec(),
+            ..Default::default()
+        };
+        for game in games {
+            db.insert(game);
+        }
+
+        db
+    }
+
+    /// Adds a game whose paths haven't been expanded yet, keeping the saves
+    /// which can't be used on this machine separately.
+    fn insert(&mut self, mut game: Game) {
+        let unavailable = game.update_paths(self.steam.as_ref(), &self.wine_prefixes);
+        if !unavailable.is_empty() {
+            self.unavailable.push(Game {
+                saves: unavailable,
+                ..game.clone()
+            });
+        }
+        if !game.saves.is_empty() {
+            self.games.push(game);
+        }
+    }
+
+    /// Finds the games whose id, title or aliases match the keyword, ignoring
+    /// case and punctuation. The best matches are first.
+    pub fn search(&self, keyword: &str) -> Vec<&Game> {
+        let keyword = normalize(keyword);
         if keyword.is_empty() {
-            eprintln!("The keyword must not be empty");
-            return;
+            return Vec::new();
+        }
+
+        let mut results: Vec<(usize, &Game)> = self
+            .games
+            .iter()
+            .filter_map(|game| {
+                std::iter::once(&game.id)
+                    .chain(std::iter::once(&game.title))
+                    .chain(game.aliases.iter())
+                    .filter_map(|name| match_score(&keyword, &normalize(name)))
+                    .max()
+                    .map(|score| (score, game))
+            })
+            .collect();
+
+        results.sort_by(|(a_score, a), (b_score, b)| {
+            b_score.cmp(a_score).then_with(|| a.title.cmp(&b.title))
+        });
+        results.dedup_by(|(_, a), (_, b)| a == b);
+        results.into_iter().map(|(_, game)| game).collect()
+    }
+
+    pub fn add(&mut self, game: Game) -> Result<()> {
+        self.games.retain(|g| *g != game);
+        self.unavailable.retain(|g| *g != game);
+        self.insert(game);
+        self.games.sort();
+        self.save()
+    }
+
+    /// Removes a custom game. Fails if any of its saves are linked, since
+    /// they couldn't be unlinked afterwards.
+    pub fn remove(&mut self, id: &str, storage_path: &Path) -> Result<()> {
+        let game = self.custom_game(id)?;
+        if game.is_linked(storage_path)? {
+            bail!(
+                "{}'s saves are linked, unlink them before removing it",
+                game.title
+            );
         }
 
-        let mut missed = true;
+        self.games.retain(|g| !(g.id == id && g.custom));
+        self.unavailable.retain(|g| !(g.id == id && g.custom));
+        self.save()
+    }
+
+    /// Changes a game, creating a custom entry for it first if it doesn't
+    /// have one. Nothing is changed if any of the saves to remove don't exist.
+    pub fn edit(&mut self, id: &str, edit: &Edit) -> Result<()> {
+        let mut game = match self.games.iter().find(|g| g.id == id) {
+            Some(game) => game.clone(),
+            None => bail!("Couldn't find a game with id {}", id),
+        };
+        for other in self.unavailable.iter().filter(|g| g.id == id) {
+            game.saves.extend(other.saves.iter().cloned());
+        }
 
-        for game in &self.games {
-            if game.id.contains(keyword) || game.title.contains(keyword) {
-                println!("Found {} ({})", game.title, game.id);
-                missed = false;
+        for save_id in &edit.removed_saves {
+            if !game.saves.iter().any(|s| s.id == *save_id) {
+                bail!("{} doesn't have a save with id {}", id, save_id);
             }
         }
 
-        if missed {
-            println!("Couldn't find any matching games");
+        if !game.custom {
+            info!(
+                "Creating a custom entry for {} to override the database's",
+                game.title
+            );
+            game.custom = true;
+        }
+
+        game.saves.retain(|s| !edit.removed_saves.contains(&s.id));
+        if let Some(title) = &edit.title {
+            game.title = title.clone();
         }
+
+        for save in &edit.saves {
+            match game.saves.iter_mut().find(|s| s.id == save.id) {
+                // Only the path changes, so constraints such as its operating
+                // systems and filters are kept.
+                Some(existing) => {
+                    existing.set_path(save.path(), &Variables::default())?;
+                    existing.kind = save.kind;
+                }
+                None => game.saves.push(save.clone()),
+            }
+        }
+
+        // Expanded the same way as when the database is loaded, so that Steam
+        // and Wine prefixes are taken in to account.
+        self.games.retain(|g| g.id != id);
+        self.unavailable.retain(|g| g.id != id);
+        self.insert(game);
+        self.games.sort();
+
+        self.save()
     }
 
-    pub fn add(&mut self, game: Game) -> Result<()> {
-        self.games.retain(|g| !(*g == game && g.custom));
-        self.games.push(game);
+    /// Changes a custom game's id, moving its saves in the storage path and
+    /// updating the links to them.
+    pub fn rename_id(&mut self, id: &str, new_id: &str, storage_path: &Path) -> Result<()> {
+        let game = self.custom_game(id)?.clone();
+
+        let in_use = |g: &Game| g.id == new_id;
+        if self.games.iter().any(in_use) || self.unavailable.iter().any(in_use) {
+            bail!("The id {} is already used by another game", new_id);
+        }
+
+        game.move_storage(storage_path, new_id)?;
+
+        for game in self.games.iter_mut().chain(self.unavailable.iter_mut()) {
+            if game.id == id {
+                game.id = new_id.to_owned();
+            }
+        }
+        self.games.sort();
+
         self.save()
     }
+
+    /// Finds the custom game with `id`, failing if there isn't one.
+    pub fn custom_game(&self, id: &str) -> Result<&Game> {
+        let custom = |g: &&Game| g.id == id && g.custom;
+        match self.games.iter().find(custom) {
+            Some(game) => Ok(game),
+            None => match self.unavailable.iter().find(custom) {
+                Some(game) => Ok(game),
+                None => bail!("There's no custom game with id {}", id),
+            },
+        }
+    }
+
+    /// Writes the games to the community layer `name`, replacing what was
+    /// previously there, and merges them in to the database. Games with
+    /// custom entries are left alone, as are games with saves in the storage
+    /// path under ids the imported entry doesn't have, since those saves
+    /// couldn't be found again. Returns the number of games imported.
+    pub fn import(&mut self, name: &str, games: Vec<Game>, storage_path: &Path) -> Result<usize> {
+        let mut written = Vec::new();
+        let mut imported = Vec::new();
+
+        for game in games {
+            let is_custom = |g: &Game| *g == game && g.custom;
+            if self.games.iter().any(is_custom) || self.unavailable.iter().any(is_custom) {
+                info!("{} has a custom entry, skipping", game.id);
+                written.push(game);
+                continue;
+            }
+
+            let stored = stored_save_ids(&storage_path.join(&game.id));
+            if let Some(id) = stored
+                .iter()
+                .find(|id| !game.saves.iter().any(|s| s.id == **id))
+            {
+                info!(
+                    "{}'s {} save is in the storage path but not in the imported entry, skipping",
+                    game.id, id
+                );
+                // Keep the current entry so that it isn't replaced the next
+                // time the database is loaded.
+                written.extend(self.entry(&game.id));
+                continue;
+            }
+
+            written.push(game.clone());
+            imported.push(game);
+        }
+
+        std::fs::create_dir_all(&self.community_path)?;
+        let path = self.community_path.join(name).with_extension("json");
+        DatabaseFile::write(written, path)?;
+
+        let count = imported.len();
+        for game in imported {
+            self.games.retain(|g| *g != game);
+            self.unavailable.retain(|g| *g != game);
+            self.insert(game);
+        }
+
+        self.games.sort();
+        Ok(count)
+    }
+
+    /// The game's entry which isn't custom, including the saves which can't
+    /// be used on this machine.
+    fn entry(&self, id: &str) -> Option<Game> {
+        let mut entry: Option<Game> = None;
+        for game in self.games.iter().chain(self.unavailable.iter()) {
+            if game.id != id || game.custom {
+                continue;
+            }
+
+            match entry.as_mut() {
+                Some(entry) => entry.saves.extend(game.saves.iter().cloned()),
+                None => entry = Some(game.clone()),
+            }
+        }
+
+        entry
+    }
+}
+
+/// The ids of the saves in a game's directory in the storage path.
+fn stored_save_ids(game_storage_path: &Path) -> Vec<String> {
+    match std::fs::read_dir(game_storage_path) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Lower cases the string and removes everything but letters and digits, so
+/// that e.g. "dark souls" matches "Dark Souls III".
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// How well the normalized keyword matches the normalized name, with higher
+/// being better, or None if it doesn't. Exact matches are best, followed by
+/// prefixes, substrings and then the keyword's characters appearing in order
+/// with few others between them.
+fn match_score(keyword: &str, name: &str) -> Option<usize> {
+    if name == keyword {
+        return Some(400);
+    }
+
+    if name.starts_with(keyword) {
+        return Some(300);
+    }
+
+    if name.contains(keyword) {
+        return Some(200);
+    }
+
+    // Short keywords would match almost everything.
+    let length = keyword.chars().count();
+    if length < 3 {
+        return None;
+    }
+
+    let mut keyword_chars = keyword.chars().peekable();
+    let mut skipped = 0;
+    let mut started = false;
+    for c in name.chars() {
+        match keyword_chars.peek() {
+            Some(&k) if k == c => {
+                keyword_chars.next();
+                started = true;
+            }
+            Some(_) if started => skipped += 1,
+            Some(_) => (),
+            None => break,
+        }
+    }
+
+    if keyword_chars.peek().is_some() || skipped > length / 2 {
+        return None;
+    }
+
+    // Long keywords can skip more than 100 characters.
+    Some(100usize.saturating_sub(skipped))
+}
+
+/// Parses a database file without loading it, migrating it to the current
+/// version.
+pub fn parse<T: AsRef<str>>(data: T) -> Result<Vec<Game>> {
+    Ok(DatabaseFile::parse(data)?.games)
+}
+
+/// Where a file written by an older `version` of saveli is kept once it has
+/// been upgraded, e.g. `user.json.v1.bak`.
+fn backup_path(path: &Path, version: usize) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{}.bak", version));
+    PathBuf::from(backup)
+}
+
+/// Moves `steam_app_id` in to `stores`, which can hold the ids of other
+/// stores.
+fn migrate_v1(db: &mut Value) {
+    let games = db["games"].as_array_mut().into_iter().flatten();
+    for game in games.filter_map(Value::as_object_mut) {
+        if let Some(id) = game.remove("steam_app_id") {
+            game.insert("stores".to_owned(), serde_json::json!({ "steam": id }));
+        }
+    }
 }
 
 #[cfg(test)]
@@ -113,6 +597,450 @@ mod tests {
         Database::load(json.to_string()).unwrap();
     }
 
+    #[test]
+    fn test_load_bundled_database() {
+        let db = Database::load(include_str!("../res/database.json")).unwrap();
+        assert!(!db.games.is_empty());
+    }
+
+    fn other_os() -> Os {
+        match Os::current() {
+            Os::Windows => Os::Linux,
+            _ => Os::Windows,
+        }
+    }
+
+    fn save_json(id: &str, os: Os) -> serde_json::Value {
+        let path = tempfile::tempdir().unwrap().into_path();
+        json!({ "id": id, "path": path, "os": [os] })
+    }
+
+    #[test]
+    fn test_load_filters_unavailable() {
+        let json = json!({
+            "version": VERSION,
+            "games": [
+                {
+                    "title": "Both",
+                    "id": "both",
+                    "saves": [save_json("current", Os::current()), save_json("other", other_os())]
+                },
+                {
+                    "title": "Other",
+                    "id": "other",
+                    "saves": [save_json("other", other_os())]
+                }
+            ]
+        });
+        let db = Database::load(json.to_string()).unwrap();
+        assert_eq!(db.games.len(), 1);
+        assert_eq!(db.games[0].saves.len(), 1);
+        assert_eq!(db.games[0].saves[0].id, "current");
+    }
+
+    #[test]
+    fn test_save_keeps_unavailable() {
+        let json = json!({
+            "version": VERSION,
+            "games": [
+                {
+                    "title": "Both",
+                    "id": "both",
+                    "custom": true,
+                    "saves": [save_json("current", Os::current()), save_json("other", other_os())]
+                },
+                {
+                    "title": "Other",
+                    "id": "other",
+                    "custom": true,
+                    "saves": [save_json("other", other_os())]
+                },
+                {
+                    "title": "Bundled",
+                    "id": "bundled",
+                    "saves": [save_json("current", Os::current())]
+                }
+            ]
+        });
+        let mut db = Database::load(json.to_string()).unwrap();
+        db.path = tempfile::tempdir().unwrap().into_path().join("user.json");
+        db.save().unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&db.path).unwrap()).unwrap();
+        assert_eq!(saved["games"].as_array().unwrap().len(), 2);
+        assert_eq!(saved["games"][0]["saves"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_new_migrates_windows_database() {
+        let storage_path = tempfile::tempdir().unwrap();
+        let save_path = tempfile::tempdir().unwrap().into_path();
+        let json = json!({
+            "version": VERSION,
+            "games": [
+                { "title": "Custom", "id": "custom", "custom": true,
+                  "saves": [{ "id": "primary", "path": save_path }] },
+                { "title": "Imported", "id": "imported",
+                  "saves": [{ "id": "primary", "path": save_path }] }
+            ]
+        });
+        std::fs::write(storage_path.path().join("windows.json"), json.to_string()).unwrap();
+
+        let db = Database::new(storage_path.path(), &[]).unwrap();
+        assert!(db.games.iter().any(|g| g.id == "custom"));
+        assert!(!storage_path.path().join("windows.json").exists());
+
+        let user = DatabaseFile::read(storage_path.path().join("user.json")).unwrap();
+        assert_eq!(user.games[0].saves[0].os, [Os::current()]);
+        let community = storage_path.path().join("community").join("migrated.json");
+        let community = DatabaseFile::read(community).unwrap();
+        assert_eq!(community.games[0].saves[0].os, [Os::Windows]);
+    }
+
+    #[test]
+    fn test_new_migrates_full_database() {
+        let storage_path = tempfile::tempdir().unwrap();
+        let json = json!({
+            "version": VERSION,
+            "games": [
+                { "title": "Custom", "id": "custom", "custom": true,
+                  "saves": [save_json("primary", Os::current())] },
+                { "title": "Terraria", "id": "terraria",
+                  "saves": [save_json("general", Os::current())] },
+                { "title": "Imported", "id": "imported",
+                  "saves": [save_json("primary", Os::current())] }
+            ]
+        });
+        std::fs::write(storage_path.path().join("database.json"), json.to_string()).unwrap();
+
+        let db = Database::new(storage_path.path(), &[]).unwrap();
+        assert!(!storage_path.path().join("database.json").exists());
+        assert!(storage_path.path().join("database.json.v2.bak").exists());
+
+        let user = DatabaseFile::read(storage_path.path().join("user.json")).unwrap();
+        assert_eq!(user.games.len(), 1);
+        assert_eq!(user.games[0].id, "custom");
+
+        let community = storage_path.path().join("community").join("migrated.json");
+        let community = DatabaseFile::read(community).unwrap();
+        assert_eq!(community.games.len(), 1);
+        assert_eq!(community.games[0].id, "imported");
+
+        assert!(db.games.iter().any(|g| g.id == "custom" && g.custom));
+        assert!(db.games.iter().any(|g| g.id == "imported"));
+    }
+
+    #[test]
+    fn test_new_merges_layers() {
+        let storage_path = tempfile::tempdir().unwrap();
+        let community_path = storage_path.path().join("community");
+        std::fs::create_dir(&community_path).unwrap();
+
+        let game = |id: &str, title: &str| json!({ "title": title, "id": id, "saves": [save_json("primary", Os::current())] });
+        let write = |path: PathBuf, games: Vec<serde_json::Value>| {
+            let json = json!({ "version": VERSION, "games": games });
+            std::fs::write(path, json.to_string()).unwrap();
+        };
+        write(
+            community_path.join("a.json"),
+            vec![game("terraria", "A"), game("stardewvalley", "A")],
+        );
+        write(community_path.join("b.json"), vec![game("terraria", "B")]);
+        write(
+            storage_path.path().join("user.json"),
+            vec![game("stardewvalley", "User")],
+        );
+
+        let db = Database::new(storage_path.path(), &[]).unwrap();
+        let find = |id: &str| db.games.iter().find(|g| g.id == id).unwrap();
+        assert_eq!(find("terraria").title, "B");
+        assert!(!find("terraria").custom);
+        assert_eq!(find("stardewvalley").title, "User");
+        assert!(find("stardewvalley").custom);
+        assert_eq!(
+            db.games.iter().filter(|g| g.id == "stardewvalley").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_import_keeps_custom() {
+        let save = tempfile::tempdir().unwrap();
+        let path = save.path().to_str().unwrap().to_owned();
+        let json = json!({
+            "version": VERSION,
+            "games": [
+                { "title": "Custom", "id": "custom", "custom": true,
+                  "saves": [{ "id": "primary", "path": path }] },
+                { "title": "Old", "id": "old", "saves": [{ "id": "primary", "path": path }] }
+            ]
+        });
+        let mut db = Database::load(json.to_string()).unwrap();
+        db.community_path = tempfile::tempdir().unwrap().into_path();
+
+        let imported = |id: &str, title: &str| Game {
+            title: title.to_owned(),
+            id: id.to_owned(),
+            saves: vec![SavePath::unexpanded("new".into(), path.clone(), vec![])],
+            ..Default::default()
+        };
+        let games = vec![
+            imported("custom", "Imported Custom"),
+            imported("old", "Imported Old"),
+            imported("other", "Other"),
+        ];
+        let storage_path = tempfile::tempdir().unwrap();
+        assert_eq!(
+            db.import("manifest", games, storage_path.path()).unwrap(),
+            2
+        );
+
+        let file = DatabaseFile::read(db.community_path.join("manifest.json")).unwrap();
+        assert_eq!(file.games.len(), 3);
+
+        let titles: Vec<_> = db.games.iter().map(|g| g.title.as_str()).collect();
+        assert_eq!(titles, vec!["Custom", "Imported Old", "Other"]);
+        assert_eq!(db.games[1].saves[0].id, "new");
+        assert_eq!(db.games[1].saves[0].expanded, save.path());
+    }
+
+    #[test]
+    fn test_import_keeps_stored_save_ids() {
+        let save = tempfile::tempdir().unwrap().into_path();
+        let path = save.to_str().unwrap().to_owned();
+        let json = json!({
+            "version": VERSION,
+            "games": [{ "title": "Old", "id": "old", "saves": [{ "id": "primary", "path": path }] }]
+        });
+        let mut db = Database::load(json.to_string()).unwrap();
+        db.community_path = tempfile::tempdir().unwrap().into_path();
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        db.games[0].link(&storage_path, false).unwrap();
+
+        let games = vec![Game {
+            title: "Imported Old".to_owned(),
+            id: "old".to_owned(),
+            saves: vec![SavePath::unexpanded("new".into(), path.clone(), vec![])],
+            ..Default::default()
+        }];
+        assert_eq!(db.import("manifest", games, &storage_path).unwrap(), 0);
+
+        let file = DatabaseFile::read(db.community_path.join("manifest.json")).unwrap();
+        assert_eq!(file.games[0].saves[0].id, "primary");
+        assert_eq!(db.games[0].saves[0].id, "primary");
+
+        db.games[0].unlink(&storage_path, false).unwrap();
+        assert!(std::fs::symlink_metadata(&save).unwrap().is_dir());
+        assert!(!storage_path.join("old").exists());
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let json = json!({
+            "version": 1,
+            "games": [{
+                "title": "Steam",
+                "id": "steam",
+                "steam_app_id": 374320,
+                "saves": [save_json("primary", Os::current())]
+            }]
+        });
+        let file = DatabaseFile::parse(json.to_string()).unwrap();
+        assert_eq!(file.version, 1);
+        assert_eq!(file.games[0].stores.steam, Some(374_320));
+    }
+
+    #[test]
+    fn test_save_backs_up_older_version() {
+        let storage_path = tempfile::tempdir().unwrap();
+        let path = storage_path.path().join("user.json");
+        let json = json!({
+            "version": 1,
+            "games": [{
+                "title": "Custom",
+                "id": "custom",
+                "saves": [save_json("primary", Os::current())]
+            }]
+        });
+        std::fs::write(&path, json.to_string()).unwrap();
+
+        let db = Database::new(storage_path.path(), &[]).unwrap();
+        db.save().unwrap();
+
+        let backup = storage_path.path().join("user.json.v1.bak");
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), json.to_string());
+        assert_eq!(DatabaseFile::read(&path).unwrap().version, VERSION);
+    }
+
+    #[test]
+    fn test_match_score() {
+        let score = |keyword, name| match_score(&normalize(keyword), &normalize(name));
+        assert!(score("dark souls", "Dark Souls III").is_some());
+        assert!(score("DARK-SOULS", "Dark Souls III") > score("souls", "Dark Souls III"));
+        assert!(score("terraria", "Terraria") > score("terr", "Terraria"));
+        assert!(score("stardw valley", "Stardew Valley").is_some());
+        assert!(score("drk souls", "Dark Souls III") > score("dk sls", "Dark Souls III"));
+        assert!(score("valley stardew", "Stardew Valley").is_none());
+        assert!(score("ds", "Dark Souls III").is_none());
+
+        let keyword = "a".repeat(300);
+        let name = format!("{}{}{}", "a".repeat(180), "b".repeat(120), "a".repeat(120));
+        assert_eq!(match_score(&keyword, &name), Some(0));
+    }
+
+    #[test]
+    fn test_search() {
+        let path = tempfile::tempdir().unwrap().into_path();
+        let game = |id: &str, title: &str, aliases: Vec<&str>| {
+            json!({
+                "title": title,
+                "id": id,
+                "aliases": aliases,
+                "saves": [{ "id": "primary", "path": path }]
+            })
+        };
+        let json = json!({
+            "version": VERSION,
+            "games": [
+                game("darksoulsiii", "Dark Souls III", vec![]),
+                game("darksouls", "Dark Souls", vec!["Dark Souls: Prepare to Die Edition"]),
+                game("stardewvalley", "Stardew Valley", vec![]),
+                game("ptde", "Something Else", vec!["Prepare to Die"]),
+            ]
+        });
+        let db = Database::load(json.to_string()).unwrap();
+
+        let ids =
+            |keyword| -> Vec<String> { db.search(keyword).iter().map(|g| g.id.clone()).collect() };
+        assert_eq!(ids("dark souls"), vec!["darksouls", "darksoulsiii"]);
+        assert_eq!(ids("prepare to die"), vec!["ptde", "darksouls"]);
+        assert_eq!(ids("stardew"), vec!["stardewvalley"]);
+        assert!(ids("!").is_empty());
+    }
+
+    fn editable_database() -> Database {
+        let json = json!({
+            "version": VERSION,
+            "games": [
+                {
+                    "title": "Custom",
+                    "id": "custom",
+                    "custom": true,
+                    "saves": [save_json("current", Os::current()), save_json("other", other_os())]
+                },
+                {
+                    "title": "Bundled",
+                    "id": "bundled",
+                    "saves": [save_json("current", Os::current()), save_json("other", other_os())]
+                }
+            ]
+        });
+        let mut db = Database::load(json.to_string()).unwrap();
+        db.path = tempfile::tempdir().unwrap().into_path().join("user.json");
+        db
+    }
+
+    fn saved_games(db: &Database) -> Vec<Game> {
+        DatabaseFile::read(&db.path).unwrap().games
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut db = editable_database();
+        let storage_path = tempfile::tempdir().unwrap();
+        db.remove("bundled", storage_path.path()).unwrap_err();
+        db.remove("custom", storage_path.path()).unwrap();
+        assert_eq!(db.games.len(), 1);
+        assert!(saved_games(&db).is_empty());
+    }
+
+    #[test]
+    fn test_remove_linked() {
+        let mut db = editable_database();
+        let src = tempfile::tempdir().unwrap().into_path();
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        let game = db.games.iter_mut().find(|g| g.custom).unwrap();
+        game.saves = vec![SavePath::new("current".to_owned(), src.to_str().unwrap()).unwrap()];
+        game.link(&storage_path, false).unwrap();
+
+        db.remove("custom", &storage_path).unwrap_err();
+        assert!(db.custom_game("custom").is_ok());
+
+        db.custom_game("custom")
+            .unwrap()
+            .unlink(&storage_path, false)
+            .unwrap();
+        db.remove("custom", &storage_path).unwrap();
+    }
+
+    #[test]
+    fn test_edit_creates_custom_entry() {
+        let mut db = editable_database();
+        let edit = Edit {
+            title: Some("Edited".to_owned()),
+            ..Default::default()
+        };
+        db.edit("bundled", &edit).unwrap();
+
+        let saved = saved_games(&db);
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0].title, "Edited");
+        assert!(saved[0].custom);
+        assert_eq!(saved[0].saves.len(), 2);
+    }
+
+    #[test]
+    fn test_edit_saves() {
+        let mut db = editable_database();
+        let path = tempfile::tempdir().unwrap().into_path();
+        let path = path.to_str().unwrap();
+        let edit = Edit {
+            saves: vec![
+                SavePath::new("current".to_owned(), path).unwrap(),
+                SavePath::new("new".to_owned(), path).unwrap(),
+            ],
+            removed_saves: vec!["other".to_owned()],
+            ..Default::default()
+        };
+        db.edit("custom", &edit).unwrap();
+
+        let saved = saved_games(&db);
+        let saves = &saved[0].saves;
+        assert_eq!(saves.len(), 2);
+        assert_eq!(saves[0].id, "current");
+        assert_eq!(saves[0].path(), path);
+        assert_eq!(saves[0].os, vec![Os::current()]);
+        assert_eq!(saves[1].id, "new");
+
+        let edit = Edit {
+            title: Some("Changed".to_owned()),
+            removed_saves: vec!["current".to_owned(), "missing".to_owned()],
+            ..Default::default()
+        };
+        db.edit("custom", &edit).unwrap_err();
+        let game = db.games.iter().find(|g| g.id == "custom").unwrap();
+        assert_eq!(game.title, "Custom");
+        assert!(game.saves.iter().any(|s| s.id == "current"));
+    }
+
+    #[test]
+    fn test_rename_id() {
+        let mut db = editable_database();
+        let storage_path = tempfile::tempdir().unwrap();
+        db.rename_id("bundled", "renamed", storage_path.path())
+            .unwrap_err();
+        db.rename_id("custom", "bundled", storage_path.path())
+            .unwrap_err();
+        db.rename_id("custom", "renamed", storage_path.path())
+            .unwrap();
+
+        let saved = saved_games(&db);
+        assert_eq!(saved[0].id, "renamed");
+        assert_eq!(saved[0].saves.len(), 2);
+        assert!(db.games.iter().any(|g| g.id == "renamed"));
+    }
+
     #[test]
     fn test_load_newer_version_fails() {
         let json = json!({ "version": VERSION + 1, "games": [] });
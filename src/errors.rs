@@ -1,3 +1,7 @@
+// error_chain! checks a cfg set by its own build script, which rustc doesn't
+// know about.
+#![allow(unexpected_cfgs)]
+
 use std::path::PathBuf;
 
 error_chain! {
@@ -22,9 +26,53 @@ error_chain! {
             display("The source is already a link to: {}", target.display())
         }
 
+        UnknownVariable(name: String) {
+            display("The variable {} couldn't be resolved", name)
+        }
+
+        InvalidPattern(pattern: String, reason: String) {
+            display("The pattern {} is invalid: {}", pattern, reason)
+        }
+
         FailedToMove(from: PathBuf, to: PathBuf) {
             display("Failed to move {} to {}", from.display(), to.display())
         }
+
+        ChecksumMismatch(path: PathBuf) {
+            display("The copy of {} doesn't match the original, so the original was kept", path.display())
+        }
+
+        RollbackFailed(error: String, rollback_error: String) {
+            display("{}. Undoing the changes made so far also failed, run recover to try again: {}", error, rollback_error)
+        }
+
+        UnfinishedJournal(path: PathBuf) {
+            display("An interrupted operation was found in {}, run recover to undo it", path.display())
+        }
+
+        CorruptJournal(reason: String) {
+            display("The journal can't be recovered, {}", reason)
+        }
+
+        GamesFailed(failed: usize, attempted: usize) {
+            display("{} of {} games failed", failed, attempted)
+        }
+
+        InvalidDatabase(problems: usize) {
+            display("Found {} problems in the database", problems)
+        }
+
+        NotEnoughSpace(required: u64, available: u64) {
+            display("Moving the saves needs {} bytes in the storage path, but only {} are available", required, available)
+        }
+
+        NotWritable(path: PathBuf) {
+            display("Can't write to {}", path.display())
+        }
+
+        PreflightFailed(problems: usize) {
+            display("Found {} problems which would stop the saves from being moved, nothing was changed", problems)
+        }
     }
 
     foreign_links {
@@ -32,5 +80,37 @@ error_chain! {
         FsExtra(fs_extra::error::Error);
         Io(std::io::Error);
         Json(serde_json::error::Error);
+        Yaml(serde_yaml::Error);
+    }
+}
+
+impl ErrorKind {
+    /// A name for the kind of error which doesn't change between versions,
+    /// for programs reading the JSON output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::DatabaseTooNew(..) => "database_too_new",
+            ErrorKind::DestinationDoesNotExist(_) => "destination_does_not_exist",
+            ErrorKind::SourceExists(_) => "source_exists",
+            ErrorKind::AlreadyLinked(_) => "already_linked",
+            ErrorKind::UnknownVariable(_) => "unknown_variable",
+            ErrorKind::InvalidPattern(..) => "invalid_pattern",
+            ErrorKind::FailedToMove(..) => "failed_to_move",
+            ErrorKind::ChecksumMismatch(_) => "checksum_mismatch",
+            ErrorKind::RollbackFailed(..) => "rollback_failed",
+            ErrorKind::UnfinishedJournal(_) => "unfinished_journal",
+            ErrorKind::CorruptJournal(_) => "corrupt_journal",
+            ErrorKind::GamesFailed(..) => "games_failed",
+            ErrorKind::InvalidDatabase(_) => "invalid_database",
+            ErrorKind::NotEnoughSpace(..) => "not_enough_space",
+            ErrorKind::NotWritable(_) => "not_writable",
+            ErrorKind::PreflightFailed(_) => "preflight_failed",
+            ErrorKind::AppDirs(_) => "app_dirs",
+            ErrorKind::FsExtra(_) => "fs_extra",
+            ErrorKind::Io(_) => "io",
+            ErrorKind::Json(_) => "json",
+            ErrorKind::Yaml(_) => "yaml",
+            _ => "other",
+        }
     }
 }
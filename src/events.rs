@@ -0,0 +1,127 @@
+use crate::errors::*;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::RwLock;
+
+type Handler = Box<dyn Fn(&Event) + Send + Sync>;
+
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+
+/// Calls `handler` with every event from now on, replacing any previous
+/// handler. Events are otherwise only logged.
+pub fn set_handler<F>(handler: F)
+where
+    F: Fn(&Event) + Send + Sync + 'static,
+{
+    if let Ok(mut current) = HANDLER.write() {
+        *current = Some(Box::new(handler));
+    }
+}
+
+pub(crate) fn emit(event: &Event) {
+    if let Ok(handler) = HANDLER.read() {
+        if let Some(handler) = handler.as_ref() {
+            handler(event);
+        }
+    }
+}
+
+/// Something which happened while linking, restoring or unlinking games.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// What would be done, in dry runs.
+    Planned {
+        game: &'a str,
+        action: &'a str,
+        path: &'a Path,
+        dest: &'a Path,
+    },
+    Moving {
+        game: &'a str,
+        from: &'a Path,
+        to: &'a Path,
+    },
+    Linked {
+        game: &'a str,
+        link: &'a Path,
+        target: &'a Path,
+    },
+    Unlinked {
+        game: &'a str,
+        link: &'a Path,
+        target: &'a Path,
+    },
+    /// How much of a save has been copied to another filesystem. The first
+    /// event for a move has nothing copied, with the estimated totals.
+    Progress {
+        from: &'a Path,
+        to: &'a Path,
+        copied_bytes: u64,
+        total_bytes: u64,
+        copied_files: u64,
+        total_files: u64,
+    },
+    RemovedDir {
+        game: &'a str,
+        path: &'a Path,
+    },
+    Skipped {
+        game: &'a str,
+        path: &'a Path,
+        state: &'a str,
+    },
+    Ignored {
+        game: &'a str,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        game: Option<&'a str>,
+        kind: &'static str,
+        message: String,
+    },
+}
+
+impl<'a> Event<'a> {
+    pub fn error(game: Option<&'a str>, error: &Error) -> Event<'a> {
+        Event::Error {
+            game,
+            kind: error.kind().name(),
+            message: error.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_event_json() {
+        let event = Event::Linked {
+            game: "a",
+            link: Path::new("/saves/a"),
+            target: Path::new("/storage/a/saves"),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({
+                "event": "linked",
+                "game": "a",
+                "link": "/saves/a",
+                "target": "/storage/a/saves",
+            })
+        );
+
+        let error: Error = ErrorKind::GamesFailed(1, 2).into();
+        assert_eq!(
+            serde_json::to_value(Event::error(None, &error)).unwrap(),
+            json!({
+                "event": "error",
+                "kind": "games_failed",
+                "message": "1 of 2 games failed",
+            })
+        );
+    }
+}
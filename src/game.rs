@@ -1,15 +1,81 @@
 use crate::database::Database;
-use crate::errors::Result;
+use crate::errors::*;
+use crate::events::{self, Event};
+use crate::journal::Journal;
 use crate::linker::Linker;
 use crate::settings::Settings;
+use crate::steam::Steam;
+use crate::transaction::Transaction;
+use crate::variables::Variables;
 use serde::{Deserialize, Serialize};
 use std::cmp::{Ord, Ordering, PartialOrd};
 use std::path::{Path, PathBuf};
 
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Os {
+    Windows,
+    Linux,
+    Macos,
+}
+
+impl Os {
+    #[cfg(windows)]
+    pub fn current() -> Os {
+        Os::Windows
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn current() -> Os {
+        Os::Macos
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn current() -> Os {
+        Os::Linux
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Os::Windows => "windows",
+            Os::Linux => "linux",
+            Os::Macos => "macos",
+        }
+    }
+}
+
+/// Whether a save is a single file or a directory.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveKind {
+    File,
+    Directory,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct SavePath {
     pub id: String,
     path: String,
+    /// The operating systems the path applies to, or all of them if empty.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub os: Vec<Os>,
+    /// What the save is expected to be. Paths of the wrong kind aren't
+    /// considered to be present.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<SaveKind>,
+    /// Glob patterns for the files within the save's directory to link. If
+    /// this or `exclude` are specified, each matching file is linked rather
+    /// than the whole directory.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Glob patterns for the files within the save's directory to leave in
+    /// place.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
     // It would be much nicer to be able to set this as part of finalizing the
     // deserialization of the SavePath. Remove update_path if this gets fixed.
     // Watch https://github.com/serde-rs/serde/issues/642
@@ -24,30 +90,358 @@ impl SavePath {
             ..Default::default()
         };
 
-        save_path.set_path(path)?;
+        save_path.set_path(path, &Variables::default())?;
         Ok(save_path)
     }
 
-    pub fn update_path(&mut self) -> Result<()> {
+    /// Creates a save without expanding its path, e.g. when it's for another
+    /// operating system. Call `update_path` before using it.
+    pub fn unexpanded(id: String, path: String, os: Vec<Os>) -> SavePath {
+        SavePath {
+            id,
+            path,
+            os,
+            ..Default::default()
+        }
+    }
+
+    /// The path as it's written in the database.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn applies_to(&self, os: Os) -> bool {
+        self.os.is_empty() || self.os.contains(&os)
+    }
+
+    pub fn update_path(&mut self, vars: &Variables) -> Result<()> {
         let path = self.path.to_owned();
-        self.set_path(&path)
+        self.set_path(&path, vars)
+    }
+
+    /// Expands the path using the first of `prefixes` it exists in, or the
+    /// first it can be expanded in. Returns whether it could be expanded.
+    fn update_path_in_prefixes(&mut self, prefixes: &[Variables]) -> bool {
+        let mut first = None;
+        for vars in prefixes {
+            if self.update_path(vars).is_err() {
+                continue;
+            }
+
+            if self.is_present() {
+                return true;
+            }
+
+            first = first.or_else(|| Some(self.expanded.clone()));
+        }
+
+        match first {
+            Some(expanded) => {
+                self.expanded = expanded;
+                true
+            }
+            None => false,
+        }
     }
 
-    pub fn set_path<T: AsRef<str>>(&mut self, path: T) -> Result<()> {
+    pub fn set_path<T: AsRef<str>>(&mut self, path: T, vars: &Variables) -> Result<()> {
         let trimmed = path.as_ref().trim();
-        if !trimmed.starts_with('$') {
-            eprintln!("The path doesn't start with a variable: {}", trimmed);
+        if !trimmed.starts_with('$') && !trimmed.starts_with('%') {
+            warn!("The path doesn't start with a variable: {}", trimmed);
         }
 
         self.path = trimmed.to_owned();
-        let expanded_str = shellexpand::env(&self.path).unwrap_or_default();
-        self.expanded = PathBuf::from(expanded_str.into_owned());
+        self.expanded = vars.expand(&self.path)?;
         if self.expanded.is_relative() {
             bail!("Found relative path: {}", self.expanded.display());
         }
 
         Ok(())
     }
+
+    /// Sets the kind from what currently exists at the expanded path, if
+    /// anything.
+    pub fn detect_kind(&mut self) {
+        if split_glob(&self.expanded).is_some() {
+            return;
+        }
+
+        self.kind = match std::fs::metadata(&self.expanded) {
+            Ok(m) if m.is_file() => Some(SaveKind::File),
+            Ok(m) if m.is_dir() => Some(SaveKind::Directory),
+            _ => None,
+        };
+    }
+}
+
+/// What saveli can tell about a save by inspecting the filesystem.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SaveState {
+    /// Nothing exists at the save's location or in the storage path.
+    Absent,
+    /// A real file or directory exists at the save's location.
+    Movable,
+    /// The save's location is a link to its storage path.
+    Linked,
+    /// The save's location is a link to somewhere other than its storage path.
+    LinkedElsewhere(PathBuf),
+    /// The save's location is a link to something which doesn't exist.
+    BrokenLink(PathBuf),
+    /// The save has been moved to the storage path but there's no link to it.
+    Unlinked,
+    /// Real files or directories exist at both the save's location and the
+    /// storage path.
+    Conflict,
+}
+
+impl SaveState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SaveState::Absent => "not present",
+            SaveState::Movable => "movable",
+            SaveState::Linked => "linked",
+            SaveState::LinkedElsewhere(_) => "linked elsewhere",
+            SaveState::BrokenLink(_) => "broken link",
+            SaveState::Unlinked => "moved but unlinked",
+            SaveState::Conflict => "conflict",
+        }
+    }
+
+    /// Whether link will attempt to move the save.
+    pub fn is_movable(&self) -> bool {
+        matches!(self, SaveState::Movable | SaveState::Conflict)
+    }
+}
+
+/// A concrete location of a save. Saves with glob patterns have one for each
+/// match.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaveLocation {
+    /// The save's id, followed by the match's path relative to the start of
+    /// the pattern for glob patterns, e.g. `saves/76561198000000000`.
+    pub id: String,
+    pub path: PathBuf,
+    /// Where the save is kept in the storage path.
+    pub dest: PathBuf,
+    /// What the path is expected to be. Paths of the wrong kind are treated
+    /// as if they don't exist.
+    pub kind: Option<SaveKind>,
+}
+
+impl SaveLocation {
+    /// Classifies the save by comparing its path with its destination.
+    pub fn state(&self) -> SaveState {
+        let stored = std::fs::symlink_metadata(&self.dest).is_ok();
+
+        if let Some(target) = Linker::link_target(&self.path) {
+            // Relative targets are relative to the link's parent.
+            let resolved = match self.path.parent() {
+                Some(parent) => parent.join(&target),
+                None => target.clone(),
+            };
+
+            return if !resolved.exists() {
+                SaveState::BrokenLink(target)
+            } else if target == self.dest {
+                SaveState::Linked
+            } else {
+                SaveState::LinkedElsewhere(target)
+            };
+        }
+
+        let present = match std::fs::symlink_metadata(&self.path) {
+            Ok(m) => match self.kind {
+                Some(SaveKind::File) => m.is_file(),
+                Some(SaveKind::Directory) => m.is_dir(),
+                None => true,
+            },
+            Err(_) => false,
+        };
+        match (present, stored) {
+            (true, true) => SaveState::Conflict,
+            (true, false) => SaveState::Movable,
+            (false, true) => SaveState::Unlinked,
+            (false, false) => SaveState::Absent,
+        }
+    }
+}
+
+/// Splits a path containing glob patterns in to the path before the first
+/// pattern and the remaining components, separated by `/`.
+fn split_glob(path: &Path) -> Option<(PathBuf, String)> {
+    let mut base = PathBuf::new();
+    let mut components = path.components();
+    while let Some(component) = components.next() {
+        let s = component.as_os_str().to_string_lossy();
+        if s.contains(['*', '?', '[']) {
+            let mut pattern = s.into_owned();
+            for rest in components {
+                pattern.push('/');
+                pattern.push_str(&rest.as_os_str().to_string_lossy());
+            }
+            return Some((base, pattern));
+        }
+        base.push(component);
+    }
+
+    None
+}
+
+/// Finds the paths under `root` which match `pattern`, returned relative to
+/// `root` with `/` separators so they can be used as ids on any platform.
+fn glob_relative(root: &Path, pattern: &str) -> Vec<String> {
+    let full = format!(
+        "{}/{}",
+        glob::Pattern::escape(&root.to_string_lossy()),
+        pattern
+    );
+
+    let paths = match glob::glob(&full) {
+        Ok(paths) => paths,
+        Err(_) => return Vec::new(),
+    };
+
+    paths
+        .filter_map(|p| p.ok())
+        .filter_map(|p| {
+            let relative = p.strip_prefix(root).ok()?;
+            let components: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            Some(components.join("/"))
+        })
+        .collect()
+}
+
+impl SavePath {
+    /// Whether anything exists at the save's path, or matches its pattern.
+    pub fn is_present(&self) -> bool {
+        match split_glob(&self.expanded) {
+            Some((base, pattern)) => !glob_relative(&base, &pattern).is_empty(),
+            None => match std::fs::symlink_metadata(&self.expanded) {
+                Ok(m) if m.file_type().is_symlink() => true,
+                Ok(m) => match self.kind {
+                    Some(SaveKind::File) => m.is_file(),
+                    Some(SaveKind::Directory) => m.is_dir(),
+                    None => true,
+                },
+                Err(_) => false,
+            },
+        }
+    }
+
+    /// The save's concrete locations. Saves without glob patterns or filters
+    /// always have exactly one. Glob patterns and filters are matched against
+    /// both the save's path and its storage path, so that moved saves can be
+    /// found again.
+    pub fn locations(&self, game_storage_path: &Path) -> Result<Vec<SaveLocation>> {
+        let dest = game_storage_path.join(&self.id);
+        let (base, pattern) = match split_glob(&self.expanded) {
+            Some(split) => split,
+            None => {
+                return self.filter(SaveLocation {
+                    id: self.id.clone(),
+                    path: self.expanded.clone(),
+                    dest,
+                    kind: self.kind,
+                })
+            }
+        };
+
+        let mut relatives = glob_relative(&base, &pattern);
+        relatives.extend(glob_relative(&dest, &pattern));
+        relatives.sort();
+        relatives.dedup();
+
+        let mut locations = Vec::new();
+        for relative in relatives {
+            locations.extend(self.filter(SaveLocation {
+                id: format!("{}/{}", self.id, relative),
+                path: base.join(&relative),
+                dest: dest.join(&relative),
+                kind: None,
+            })?);
+        }
+
+        Ok(locations)
+    }
+
+    /// Splits a directory in to a location for each file matching the include
+    /// and exclude patterns, found in either the directory or its storage
+    /// path.
+    fn filter(&self, location: SaveLocation) -> Result<Vec<SaveLocation>> {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return Ok(vec![location]);
+        }
+
+        let include = patterns(&self.include)?;
+        let exclude = patterns(&self.exclude)?;
+
+        // Directories which were linked as a whole, and file saves, are left
+        // as they are.
+        match std::fs::symlink_metadata(&location.path) {
+            Ok(md) if !md.is_dir() => return Ok(vec![location]),
+            _ => (),
+        }
+
+        let mut relatives = Vec::new();
+        walk_files(&location.path, "", &mut relatives);
+        walk_files(&location.dest, "", &mut relatives);
+        relatives.retain(|r| {
+            (include.is_empty() || include.iter().any(|p| p.matches(r)))
+                && !exclude.iter().any(|p| p.matches(r))
+        });
+        relatives.sort();
+        relatives.dedup();
+
+        Ok(relatives
+            .into_iter()
+            .map(|relative| SaveLocation {
+                id: format!("{}/{}", location.id, relative),
+                path: location.path.join(&relative),
+                dest: location.dest.join(&relative),
+                kind: None,
+            })
+            .collect())
+    }
+}
+
+/// Parses include or exclude patterns. Invalid patterns are an error rather
+/// than being skipped, since skipping every include pattern would link
+/// everything.
+fn patterns(patterns: &[String]) -> Result<Vec<glob::Pattern>> {
+    patterns
+        .iter()
+        .map(|p| {
+            glob::Pattern::new(p)
+                .map_err(|e| ErrorKind::InvalidPattern(p.clone(), e.msg.to_owned()).into())
+        })
+        .collect()
+}
+
+/// Collects the paths of the files and links within `dir`, relative to the
+/// directory the walk started in.
+fn walk_files(dir: &Path, prefix: &str, files: &mut Vec<String>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let relative = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => walk_files(&entry.path(), &relative, files),
+            Ok(_) => files.push(relative),
+            Err(_) => (),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -57,6 +451,17 @@ pub struct Game {
     #[serde(default)]
     #[serde(skip_serializing_if = "is_false")]
     pub custom: bool,
+    /// Other names the game is known by.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Stores::is_empty")]
+    pub stores: Stores,
+    /// Anything users should know about the game's saves.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
     pub saves: Vec<SavePath>,
 }
 
@@ -64,6 +469,25 @@ fn is_false(v: &bool) -> bool {
     !*v
 }
 
+/// The game's ids in the stores it's sold in.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Stores {
+    /// Used to resolve the Steam path variables, and to find Windows saves in
+    /// the game's Proton prefix on other operating systems.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steam: Option<u32>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gog: Option<u64>,
+}
+
+impl Stores {
+    fn is_empty(&self) -> bool {
+        self.steam.is_none() && self.gog.is_none()
+    }
+}
+
 impl PartialOrd for Game {
     fn partial_cmp(&self, other: &Game) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -95,60 +519,349 @@ impl PartialEq for Game {
 }
 
 impl Game {
-    pub fn link_all(db: &Database, settings: &Settings) -> Result<()> {
-        let movable = Game::all_with_movable_saves(&db.games);
-        println!(
+    /// Derives a game id from its title in the same style as the bundled
+    /// database, e.g. `darksoulsiii`.
+    pub fn id_from_title(title: &str) -> String {
+        title
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    }
+
+    /// Expands the paths of the saves which can be used on this machine and
+    /// returns those which can't, either because they're for other operating
+    /// systems or because their variables couldn't be resolved.
+    ///
+    /// On other operating systems Windows saves are looked for in the game's
+    /// Proton prefix and then in `wine_prefixes`, using the first prefix the
+    /// save exists in.
+    pub fn update_paths(
+        &mut self,
+        steam: Option<&Steam>,
+        wine_prefixes: &[PathBuf],
+    ) -> Vec<SavePath> {
+        let os = Os::current();
+        let vars = Variables::for_game(steam, self.stores.steam);
+        let prefixes: Vec<Variables> = if os == Os::Windows {
+            Vec::new()
+        } else {
+            vars.proton()
+                .into_iter()
+                .chain(wine_prefixes.iter().map(|p| vars.wine(p)))
+                .collect()
+        };
+
+        let mut unavailable = Vec::new();
+        let mut in_prefixes = Vec::new();
+        for mut save in std::mem::take(&mut self.saves) {
+            if save.applies_to(os) {
+                if save.update_path(&vars).is_ok() {
+                    self.saves.push(save);
+                } else {
+                    unavailable.push(save);
+                }
+            } else if save.applies_to(Os::Windows) && save.update_path_in_prefixes(&prefixes) {
+                in_prefixes.push(save);
+            } else {
+                unavailable.push(save);
+            }
+        }
+
+        // Saves found in a prefix share their id with the native saves, so
+        // they're only used when the native save isn't present.
+        for save in in_prefixes {
+            let native_present = self.saves.iter().any(|s| s.id == save.id && s.is_present());
+            let present = save.is_present();
+            let has_native = self.saves.iter().any(|s| s.id == save.id);
+
+            if !has_native || (present && !native_present) {
+                let (native, saves) = std::mem::take(&mut self.saves)
+                    .into_iter()
+                    .partition(|s| s.id == save.id);
+                self.saves = saves;
+                unavailable.extend::<Vec<SavePath>>(native);
+                self.saves.push(save);
+            } else {
+                unavailable.push(save);
+            }
+        }
+
+        unavailable
+    }
+
+    pub fn link_all(db: &Database, settings: &Settings, selection: &Selection) -> Result<Report> {
+        let movable: Vec<&Game> = Game::all_with_movable_saves(&db.games, &settings.storage_path)
+            .into_iter()
+            .filter(|g| selection.includes(g))
+            .collect();
+        info!(
             "Found {} games with saves in their standard locations",
             movable.len()
         );
 
-        for game in movable {
-            if settings.game_is_ignored(&game.id) {
-                println!("{} is ignored, skipping", game.title);
-            } else if let Err(e) = game.link(&settings.storage_path, settings.dry_run) {
-                eprintln!("{}", e);
-            }
-        }
+        let linked: Vec<&Game> = movable
+            .iter()
+            .copied()
+            .filter(|g| !settings.game_is_ignored(&g.id) || selection.names(g))
+            .collect();
+        Game::check_preflight(&linked, &settings.storage_path, settings.dry_run)?;
 
-        Ok(())
+        Ok(Game::run_all(
+            &db.games,
+            &movable,
+            settings,
+            selection,
+            |game| game.link_unchecked(&settings.storage_path, settings.dry_run),
+        ))
     }
 
-    pub fn restore_all(db: &Database, settings: &Settings) -> Result<()> {
-        let restorable = Game::all_with_moved_saves(&db.games, &settings.storage_path);
-        println!(
+    pub fn restore_all(
+        db: &Database,
+        settings: &Settings,
+        selection: &Selection,
+    ) -> Result<Report> {
+        let restorable: Vec<&Game> = Game::all_with_moved_saves(&db.games, &settings.storage_path)
+            .into_iter()
+            .filter(|g| selection.includes(g))
+            .collect();
+        info!(
             "Found {} games with saves moved to {}",
             restorable.len(),
             settings.storage_path.display()
         );
 
-        for game in restorable {
-            if settings.game_is_ignored(&game.id) {
-                println!("{} is ignored, skipping", game.title);
-            } else if let Err(e) = game.restore(&settings.storage_path, settings.dry_run) {
-                eprintln!("{}", e);
+        Ok(Game::run_all(
+            &db.games,
+            &restorable,
+            settings,
+            selection,
+            |game| game.restore(&settings.storage_path, settings.dry_run),
+        ))
+    }
+
+    pub fn unlink_all(db: &Database, settings: &Settings, selection: &Selection) -> Result<Report> {
+        let restorable: Vec<&Game> = Game::all_with_moved_saves(&db.games, &settings.storage_path)
+            .into_iter()
+            .filter(|g| selection.includes(g))
+            .collect();
+        info!("Found {} games with moved saves", restorable.len());
+
+        Ok(Game::run_all(
+            &db.games,
+            &restorable,
+            settings,
+            selection,
+            |game| game.unlink(&settings.storage_path, settings.dry_run),
+        ))
+    }
+
+    /// Checks that the games' saves can be moved to the storage path before
+    /// any of them are. There must be enough space for the saves which have to
+    /// be copied to another filesystem, and it must be possible to write to the
+    /// saves' parent directories and the storage path, as well as to the
+    /// directories which have to be copied, so that they can be removed
+    /// afterwards.
+    pub fn preflight(games: &[&Game], storage_path: &Path) -> Vec<Error> {
+        let mut required = 0;
+        let mut dirs: Vec<PathBuf> = vec![storage_path.to_path_buf()];
+        let mut add_dir = |dir: &Path| {
+            if !dirs.iter().any(|d| d == dir) {
+                dirs.push(dir.to_path_buf());
+            }
+        };
+
+        for game in games {
+            // Linking the game reports why its saves can't be found.
+            let locations = match game.locations(&storage_path.join(&game.id)) {
+                Ok(locations) => locations,
+                Err(_) => continue,
+            };
+
+            for location in locations {
+                if !location.state().is_movable() {
+                    continue;
+                }
+
+                if !Linker::same_filesystem(&location.path, storage_path) {
+                    required += Linker::size_on_disk(&location.path).bytes;
+                    if location.path.is_dir() {
+                        add_dir(&location.path);
+                    }
+                }
+
+                if let Some(parent) = location.path.parent() {
+                    add_dir(parent);
+                }
+            }
+        }
+
+        let mut problems: Vec<Error> = Vec::new();
+        if required > 0 {
+            debug!(
+                "{} bytes of saves have to be copied to the storage path",
+                required
+            );
+            match Linker::available_space(storage_path) {
+                Ok(available) if available < required => {
+                    problems.push(ErrorKind::NotEnoughSpace(required, available).into())
+                }
+                Ok(_) => (),
+                Err(e) => problems.push(e),
+            }
+        }
+
+        for dir in dirs {
+            if !Linker::is_writable(&dir) {
+                problems.push(ErrorKind::NotWritable(dir).into());
+            }
+        }
+
+        problems
+    }
+
+    /// Logs and reports the problems found by `preflight`, failing if there
+    /// are any unless it's a dry run.
+    fn check_preflight(games: &[&Game], storage_path: &Path, dry_run: bool) -> Result<()> {
+        let problems = Game::preflight(games, storage_path);
+        for problem in &problems {
+            if dry_run {
+                warn!("{}", problem);
+            } else {
+                error!("{}", problem);
             }
+            events::emit(&Event::error(None, problem));
+        }
+
+        if !problems.is_empty() && !dry_run {
+            bail!(ErrorKind::PreflightFailed(problems.len()));
         }
 
         Ok(())
     }
 
-    pub fn unlink_all(db: &Database, settings: &Settings) -> Result<()> {
-        let restorable = Game::all_with_moved_saves(&db.games, &settings.storage_path);
-        println!("Found {} games with moved saves", restorable.len());
+    /// Calls `f` for each of the selected games which have something to do
+    /// and aren't ignored, carrying on when it fails for a game.
+    fn run_all<F>(
+        games: &[Game],
+        actionable: &[&Game],
+        settings: &Settings,
+        selection: &Selection,
+        f: F,
+    ) -> Report
+    where
+        F: Fn(&Game) -> Result<()>,
+    {
+        let mut report = Report::default();
 
-        for game in restorable {
-            if settings.game_is_ignored(&game.id) {
-                println!("{} is ignored, skipping", game.title);
-            } else if let Err(e) = game.unlink(&settings.storage_path, settings.dry_run) {
-                eprintln!("{}", e);
+        for game in games.iter().filter(|g| selection.includes(g)) {
+            if !actionable.contains(&game) {
+                report.nothing_to_do.push(game.id.clone());
+            } else if settings.game_is_ignored(&game.id) && !selection.names(game) {
+                info!("{} is ignored, skipping", game.title);
+                events::emit(&Event::Ignored { game: &game.id });
+                report.ignored.push(game.id.clone());
+            } else {
+                match f(game) {
+                    Ok(()) => report.succeeded.push(game.id.clone()),
+                    Err(e) => {
+                        error!("{}", e);
+                        events::emit(&Event::error(Some(&game.id), &e));
+                        report.failed.push((game.id.clone(), e));
+                    }
+                }
             }
         }
 
-        Ok(())
+        report
+    }
+
+    /// The state of every save of every game. Saves which aren't present are
+    /// omitted.
+    pub fn status_all<'g>(db: &'g Database, settings: &Settings) -> Result<Vec<SaveStatus<'g>>> {
+        let mut statuses = Vec::new();
+        for game in &db.games {
+            for location in game.locations(&settings.storage_path.join(&game.id))? {
+                let state = location.state();
+                if state != SaveState::Absent {
+                    statuses.push(SaveStatus {
+                        game,
+                        location,
+                        state,
+                    });
+                }
+            }
+        }
+
+        Ok(statuses)
+    }
+
+    /// The games which match every filter which is set.
+    pub fn list_all<'g>(
+        db: &'g Database,
+        settings: &Settings,
+        filter: &ListFilter,
+    ) -> Result<Vec<Listing<'g>>> {
+        let mut listings = Vec::new();
+        for game in &db.games {
+            let listing = Listing {
+                game,
+                present: game.has_present_saves(),
+                linked: game.is_linked(&settings.storage_path)?,
+                ignored: settings.game_is_ignored(&game.id),
+            };
+
+            if (!filter.present || listing.present)
+                && (!filter.linked || listing.linked)
+                && (!filter.custom || game.custom)
+                && (!filter.ignored || listing.ignored)
+            {
+                listings.push(listing);
+            }
+        }
+
+        Ok(listings)
+    }
+
+    /// The state and size of every location of each of the game's saves.
+    pub fn saves_info(&self, storage_path: &Path) -> Result<Vec<SaveInfo<'_>>> {
+        let game_storage_path = storage_path.join(&self.id);
+        let mut saves = Vec::new();
+        for save in &self.saves {
+            saves.push(SaveInfo {
+                save,
+                locations: save
+                    .locations(&game_storage_path)?
+                    .into_iter()
+                    .map(|location| {
+                        let state = location.state();
+                        let data = match state {
+                            SaveState::Absent | SaveState::BrokenLink(_) => None,
+                            SaveState::Linked | SaveState::Unlinked => Some(&location.dest),
+                            SaveState::LinkedElsewhere(_) => None,
+                            SaveState::Movable | SaveState::Conflict => Some(&location.path),
+                        };
+                        let size = data.map(|data| Linker::size_on_disk(data).bytes);
+                        LocationInfo {
+                            location,
+                            state,
+                            size,
+                        }
+                    })
+                    .collect(),
+            });
+        }
+
+        Ok(saves)
     }
 
-    fn all_with_movable_saves(games: &[Game]) -> Vec<&Game> {
-        games.iter().filter(|g| g.has_movable_saves()).collect()
+    /// Games whose saves can't be checked, e.g. because of an invalid
+    /// pattern, are included so that linking them reports why.
+    fn all_with_movable_saves<'g>(games: &'g [Game], storage_path: &Path) -> Vec<&'g Game> {
+        games
+            .iter()
+            .filter(|g| g.has_movable_saves(storage_path).unwrap_or(true))
+            .collect()
     }
 
     /// Returns games which have saves in the storage path.
@@ -161,137 +874,522 @@ impl Game {
     }
 
     /// Attempts to move the game's save paths to the storage location and
-    /// create corresponding links.
+    /// create corresponding links, after checking that they can be with
+    /// `preflight`. If any step fails, the steps already completed are undone.
     pub fn link(&self, storage_path: &Path, dry_run: bool) -> Result<()> {
-        let game_storage_path = storage_path.join(&self.id);
-        if !dry_run {
-            #[cfg(windows)]
-            Linker::verify_reparse_privilege()?;
+        Game::check_preflight(&[self], storage_path, dry_run)?;
+        self.link_unchecked(storage_path, dry_run)
+    }
 
-            if let Err(e) = std::fs::create_dir_all(&game_storage_path) {
-                if e.kind() != std::io::ErrorKind::AlreadyExists {
-                    return Err(e.into());
-                }
-            }
+    fn link_unchecked(&self, storage_path: &Path, dry_run: bool) -> Result<()> {
+        if dry_run {
+            return self.link_with(storage_path, None);
+        }
+
+        #[cfg(windows)]
+        Linker::verify_reparse_privilege()?;
+
+        let journal = Journal::create(storage_path, &self.id)?;
+        Transaction::run(Some(journal), |tx| self.link_with(storage_path, Some(tx)))
+    }
+
+    /// Moves the game's directory in the storage path to the one for `new_id`,
+    /// and updates the links to the saves within it.
+    pub fn move_storage(&self, storage_path: &Path, new_id: &str) -> Result<()> {
+        let old_path = storage_path.join(&self.id);
+        let new_path = storage_path.join(new_id);
+        if !old_path.exists() {
+            return Ok(());
+        }
+
+        if std::fs::symlink_metadata(&new_path).is_ok() {
+            bail!(ErrorKind::SourceExists(new_path));
         }
 
-        for s in &self.saves {
-            let dest = game_storage_path.join(&s.id);
+        let linked: Vec<SaveLocation> = self
+            .locations(&old_path)?
+            .into_iter()
+            .filter(|l| l.state() == SaveState::Linked)
+            .collect();
+
+        #[cfg(windows)]
+        Linker::verify_reparse_privilege()?;
+
+        let journal = Journal::create(storage_path, &self.id)?;
+        Transaction::run(Some(journal), |tx| {
+            info!("Moving {} to {}", old_path.display(), new_path.display());
+            events::emit(&Event::Moving {
+                game: &self.id,
+                from: &old_path,
+                to: &new_path,
+            });
+            tx.move_item(&old_path, &new_path)?;
+
+            for location in &linked {
+                let relative = location
+                    .dest
+                    .strip_prefix(&old_path)
+                    .expect("Locations are within the game's storage path");
+                let dest = new_path.join(relative);
+                debug!(
+                    "Updating the link at {} to {}",
+                    location.path.display(),
+                    dest.display()
+                );
+                tx.remove_link(&location.path)?;
+                tx.symlink(&location.path, &dest)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn link_with(&self, storage_path: &Path, mut tx: Option<&mut Transaction>) -> Result<()> {
+        let game_storage_path = storage_path.join(&self.id);
+
+        for location in self.locations(&game_storage_path)? {
+            let state = location.state();
+            if !state.is_movable() {
+                info!(
+                    "{}'s {} is {}, skipping",
+                    self.title,
+                    location.path.display(),
+                    state.label()
+                );
+                events::emit(&Event::Skipped {
+                    game: &self.id,
+                    path: &location.path,
+                    state: state.label(),
+                });
+                continue;
+            }
 
-            println!(
+            info!(
                 "Linking {}'s {} to {}",
                 self.title,
-                s.expanded.display(),
-                dest.display()
+                location.path.display(),
+                location.dest.display()
             );
 
-            if !dry_run {
-                println!("Moving {} to {}", s.expanded.display(), dest.display());
-                Linker::move_item(&s.expanded, &dest)?;
-                println!(
-                    "Creating a link from {} to {}",
-                    s.expanded.display(),
-                    dest.display()
-                );
-                Linker::symlink(&s.expanded, &dest)?;
+            let tx = match tx.as_mut() {
+                Some(tx) => tx,
+                None => {
+                    events::emit(&Event::Planned {
+                        game: &self.id,
+                        action: "link",
+                        path: &location.path,
+                        dest: &location.dest,
+                    });
+                    continue;
+                }
+            };
+
+            if let Some(parent) = location.dest.parent() {
+                tx.create_dir_all(parent)?;
             }
+
+            debug!(
+                "Moving {} to {}",
+                location.path.display(),
+                location.dest.display()
+            );
+            events::emit(&Event::Moving {
+                game: &self.id,
+                from: &location.path,
+                to: &location.dest,
+            });
+            tx.move_item(&location.path, &location.dest)?;
+            debug!(
+                "Creating a link from {} to {}",
+                location.path.display(),
+                location.dest.display()
+            );
+            tx.symlink(&location.path, &location.dest)?;
+            events::emit(&Event::Linked {
+                game: &self.id,
+                link: &location.path,
+                target: &location.dest,
+            });
         }
 
         Ok(())
     }
 
     /// If saves exist, it will attempt to create links. It will fail if real
-    /// files or directories already exist.
+    /// files or directories already exist, removing any links it created.
     pub fn restore(&self, storage_path: &Path, dry_run: bool) -> Result<()> {
-        if !dry_run {
-            #[cfg(windows)]
-            Linker::verify_reparse_privilege()?;
+        if dry_run {
+            return self.restore_with(storage_path, None);
         }
 
-        for s in &self.saves {
-            let dest = storage_path.join(&self.id).join(&s.id);
-            println!(
+        #[cfg(windows)]
+        Linker::verify_reparse_privilege()?;
+
+        let journal = Journal::create(storage_path, &self.id)?;
+        Transaction::run(Some(journal), |tx| {
+            self.restore_with(storage_path, Some(tx))
+        })
+    }
+
+    fn restore_with(&self, storage_path: &Path, mut tx: Option<&mut Transaction>) -> Result<()> {
+        for location in self.locations(&storage_path.join(&self.id))? {
+            if std::fs::symlink_metadata(&location.dest).is_err() {
+                info!(
+                    "{}'s {} hasn't been moved to the storage path, skipping",
+                    self.title,
+                    location.path.display()
+                );
+                events::emit(&Event::Skipped {
+                    game: &self.id,
+                    path: &location.path,
+                    state: location.state().label(),
+                });
+                continue;
+            }
+
+            info!(
                 "Restoring {}'s {} from {}",
                 self.title,
-                s.expanded.display(),
-                dest.display()
+                location.path.display(),
+                location.dest.display()
             );
 
-            if !dry_run {
-                Linker::symlink(&s.expanded, &dest)?;
+            let tx = match tx.as_mut() {
+                Some(tx) => tx,
+                None => {
+                    events::emit(&Event::Planned {
+                        game: &self.id,
+                        action: "restore",
+                        path: &location.path,
+                        dest: &location.dest,
+                    });
+                    continue;
+                }
+            };
+
+            if let Some(parent) = location.path.parent() {
+                tx.create_dir_all(parent)?;
             }
+            tx.symlink(&location.path, &location.dest)?;
+            events::emit(&Event::Linked {
+                game: &self.id,
+                link: &location.path,
+                target: &location.dest,
+            });
         }
 
         Ok(())
     }
 
-    /// The inverse of link.
+    /// The inverse of link. If any step fails, the steps already completed are
+    /// undone.
     pub fn unlink(&self, storage_path: &Path, dry_run: bool) -> Result<()> {
-        if !dry_run {
-            #[cfg(windows)]
-            Linker::verify_reparse_privilege()?;
+        if dry_run {
+            return self.unlink_with(storage_path, None);
         }
 
-        for s in &self.saves {
-            let dest = storage_path.join(&self.id).join(&s.id);
-            // TODO: Check it exists
-            println!(
+        #[cfg(windows)]
+        Linker::verify_reparse_privilege()?;
+
+        let journal = Journal::create(storage_path, &self.id)?;
+        Transaction::run(Some(journal), |tx| self.unlink_with(storage_path, Some(tx)))
+    }
+
+    fn unlink_with(&self, storage_path: &Path, mut tx: Option<&mut Transaction>) -> Result<()> {
+        let game_storage_path = storage_path.join(&self.id);
+
+        for location in self.locations(&game_storage_path)? {
+            let linked = match location.state() {
+                SaveState::Linked => true,
+                SaveState::Unlinked => false,
+                ref state @ (SaveState::Absent | SaveState::Movable) => {
+                    info!(
+                        "{}'s {} isn't linked, skipping",
+                        self.title,
+                        location.path.display()
+                    );
+                    events::emit(&Event::Skipped {
+                        game: &self.id,
+                        path: &location.path,
+                        state: state.label(),
+                    });
+                    continue;
+                }
+                SaveState::BrokenLink(ref target) if *target == location.dest => {
+                    bail!(ErrorKind::DestinationDoesNotExist(location.dest))
+                }
+                SaveState::LinkedElsewhere(target) | SaveState::BrokenLink(target) => {
+                    bail!(ErrorKind::AlreadyLinked(target))
+                }
+                SaveState::Conflict => bail!(ErrorKind::SourceExists(location.path)),
+            };
+
+            info!(
                 "Unlinking {}'s {} from {}",
                 self.title,
-                s.expanded.display(),
-                dest.display()
+                location.path.display(),
+                location.dest.display()
+            );
+
+            let tx = match tx.as_mut() {
+                Some(tx) => tx,
+                None => {
+                    events::emit(&Event::Planned {
+                        game: &self.id,
+                        action: "unlink",
+                        path: &location.path,
+                        dest: &location.dest,
+                    });
+                    continue;
+                }
+            };
+
+            if linked {
+                debug!("Removing {}", location.path.display());
+                tx.remove_link(&location.path)?;
+                events::emit(&Event::Unlinked {
+                    game: &self.id,
+                    link: &location.path,
+                    target: &location.dest,
+                });
+            } else if let Some(parent) = location.path.parent() {
+                tx.create_dir_all(parent)?;
+            }
+
+            debug!(
+                "Moving {} to {}",
+                location.dest.display(),
+                location.path.display()
             );
+            events::emit(&Event::Moving {
+                game: &self.id,
+                from: &location.dest,
+                to: &location.path,
+            });
+            tx.move_item(&location.dest, &location.path)?;
 
-            if !dry_run {
-                println!("Removing {}", s.expanded.display());
-                std::fs::remove_dir(&s.path)?;
-                println!("Moving {} to {}", dest.display(), s.expanded.display());
-                Linker::move_item(&dest, &s.expanded)?;
+            // Glob matches are stored in directories named after the save.
+            let parents = location.dest.ancestors().skip(1);
+            for dir in parents.take_while(|p| *p != game_storage_path) {
+                if !is_empty_dir(dir) {
+                    break;
+                }
+                tx.remove_dir(dir)?;
             }
         }
 
-        if !dry_run {
-            let game_storage_path = storage_path.join(&self.id);
-            println!("Removing {}", game_storage_path.display());
-            std::fs::remove_dir(game_storage_path)?;
+        if let Some(tx) = tx {
+            if is_empty_dir(&game_storage_path) {
+                debug!("Removing {}", game_storage_path.display());
+                tx.remove_dir(&game_storage_path)?;
+                events::emit(&Event::RemovedDir {
+                    game: &self.id,
+                    path: &game_storage_path,
+                });
+            } else if game_storage_path.exists() {
+                info!(
+                    "{} isn't empty, leaving it in place",
+                    game_storage_path.display()
+                );
+            }
         }
 
         Ok(())
     }
 
-    fn has_movable_saves(&self) -> bool {
-        self.saves
-            .iter()
-            .any(|s| match std::fs::symlink_metadata(&s.expanded) {
-                Ok(md) => !md.file_type().is_symlink(),
-                Err(_) => false,
-            })
+    fn locations(&self, game_storage_path: &Path) -> Result<Vec<SaveLocation>> {
+        let mut locations = Vec::new();
+        for save in &self.saves {
+            locations.extend(save.locations(game_storage_path)?);
+        }
+
+        Ok(locations)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::game::{Game, SavePath};
+    /// Whether any of the game's saves exist on this machine, including ones
+    /// which have been linked.
+    pub fn has_present_saves(&self) -> bool {
+        self.saves.iter().any(SavePath::is_present)
+    }
 
-    #[test]
-    fn test_all_with_moved_saves_matches() {
-        let game = Game {
-            id: "gameid".to_owned(),
+    /// Whether any of the game's saves are linked to the storage path.
+    pub fn is_linked(&self, storage_path: &Path) -> Result<bool> {
+        Ok(self
+            .locations(&storage_path.join(&self.id))?
+            .iter()
+            .any(|l| l.state() == SaveState::Linked))
+    }
+
+    /// Whether any locations of the save with `save_id` are linked to the
+    /// storage path.
+    pub fn save_is_linked(&self, storage_path: &Path, save_id: &str) -> Result<bool> {
+        let game_storage_path = storage_path.join(&self.id);
+        for save in self.saves.iter().filter(|s| s.id == save_id) {
+            if save
+                .locations(&game_storage_path)?
+                .iter()
+                .any(|l| l.state() == SaveState::Linked)
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn has_movable_saves(&self, storage_path: &Path) -> Result<bool> {
+        Ok(self
+            .locations(&storage_path.join(&self.id))?
+            .iter()
+            .any(|l| l.state().is_movable()))
+    }
+}
+
+/// What happened to each of the games selected for `link_all`, `restore_all`
+/// or `unlink_all`, by id.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub succeeded: Vec<String>,
+    pub ignored: Vec<String>,
+    /// Games without saves to link, or without saves in the storage path to
+    /// restore or unlink.
+    pub nothing_to_do: Vec<String>,
+    pub failed: Vec<(String, Error)>,
+}
+
+impl Report {
+    /// Fails if any of the games failed.
+    pub fn result(&self) -> Result<()> {
+        if !self.failed.is_empty() {
+            let attempted = self.succeeded.len() + self.failed.len();
+            bail!(ErrorKind::GamesFailed(self.failed.len(), attempted));
+        }
+
+        Ok(())
+    }
+}
+
+/// The games which `link_all`, `restore_all` and `unlink_all` apply to.
+/// Without any ids or patterns, every game is selected.
+#[derive(Debug, Default)]
+pub struct Selection {
+    /// Games which are named explicitly are processed even if they're
+    /// ignored.
+    pub ids: Vec<String>,
+    /// Glob patterns matched against game ids and titles, ignoring case.
+    pub patterns: Vec<glob::Pattern>,
+    pub excluded: Vec<String>,
+}
+
+impl Selection {
+    pub fn includes(&self, game: &Game) -> bool {
+        if self.excluded.contains(&game.id) {
+            return false;
+        }
+
+        let options = glob::MatchOptions {
+            case_sensitive: false,
+            ..Default::default()
+        };
+        let matches = |p: &glob::Pattern| {
+            p.matches_with(&game.id, options) || p.matches_with(&game.title, options)
+        };
+
+        (self.ids.is_empty() && self.patterns.is_empty())
+            || self.names(game)
+            || self.patterns.iter().any(matches)
+    }
+
+    /// Whether the game was selected by its id.
+    pub fn names(&self, game: &Game) -> bool {
+        self.ids.contains(&game.id)
+    }
+}
+
+/// The filters for `Game::list_all`.
+#[derive(Debug, Default)]
+pub struct ListFilter {
+    pub present: bool,
+    pub linked: bool,
+    pub custom: bool,
+    pub ignored: bool,
+}
+
+/// A game returned by `Game::list_all`.
+#[derive(Debug)]
+pub struct Listing<'g> {
+    pub game: &'g Game,
+    /// Whether any of its saves exist on this machine.
+    pub present: bool,
+    /// Whether any of its saves are linked to the storage path.
+    pub linked: bool,
+    pub ignored: bool,
+}
+
+/// A location of a save returned by `Game::status_all`.
+#[derive(Debug)]
+pub struct SaveStatus<'g> {
+    pub game: &'g Game,
+    pub location: SaveLocation,
+    pub state: SaveState,
+}
+
+/// A save returned by `Game::saves_info`.
+#[derive(Debug)]
+pub struct SaveInfo<'g> {
+    pub save: &'g SavePath,
+    /// Empty if nothing matches the save's path.
+    pub locations: Vec<LocationInfo>,
+}
+
+#[derive(Debug)]
+pub struct LocationInfo {
+    pub location: SaveLocation,
+    pub state: SaveState,
+    /// The size of the save's data, wherever it is, or `None` if it doesn't
+    /// exist or is linked elsewhere.
+    pub size: Option<u64>,
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    match std::fs::read_dir(path) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrorKind;
+    use crate::game::{Game, SaveKind, SavePath, SaveState, Selection, Stores};
+    use crate::linker::Linker;
+    use crate::settings::Settings;
+
+    /// A game with a single save at `src`, which may contain glob patterns.
+    fn game_with_save(src: &std::path::Path) -> Game {
+        Game {
+            id: "gameid".to_owned(),
+            saves: vec![SavePath::new("saveid".to_owned(), src.to_str().unwrap()).unwrap()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_all_with_moved_saves_matches() {
+        let game = Game {
+            id: "gameid".to_owned(),
             ..Default::default()
         };
         let storage_path = tempfile::tempdir().unwrap().into_path();
         std::fs::create_dir(storage_path.join(&game.id)).unwrap();
-        assert_eq!(
-            Game::all_with_moved_saves(&vec![game], &storage_path).len(),
-            1
-        )
+        assert_eq!(Game::all_with_moved_saves(&[game], &storage_path).len(), 1)
     }
 
     #[test]
     fn test_all_with_moved_saves_empty() {
         let storage_path = tempfile::tempdir().unwrap().into_path();
         assert_eq!(
-            Game::all_with_moved_saves(&vec![Game::default()], &storage_path).len(),
+            Game::all_with_moved_saves(&[Game::default()], &storage_path).len(),
             0
         )
     }
@@ -337,7 +1435,7 @@ mod tests {
         };
         let storage_path = tempfile::tempdir().unwrap().into_path();
         let dest = storage_path.join(&game.id).join("saveid");
-        std::fs::create_dir_all(&storage_path.join(&game.id)).unwrap();
+        std::fs::create_dir_all(storage_path.join(&game.id)).unwrap();
         std::fs::File::create(&dest).unwrap();
         Game::link(&game, &storage_path, false).unwrap();
     }
@@ -369,5 +1467,645 @@ mod tests {
     #[test]
     fn test_restore_existing_file() {}
 
-    // TODO: Unlink tests
+    #[test]
+    fn test_link_rolls_back_on_failure() {
+        let c = tempfile::tempdir().unwrap();
+        let first = c.path().join("first");
+        let second = c.path().join("second");
+        std::fs::create_dir(&first).unwrap();
+        std::fs::File::create(&second).unwrap();
+        let game = Game {
+            id: "gameid".to_owned(),
+            saves: vec![
+                SavePath::new("a".to_owned(), first.to_str().unwrap()).unwrap(),
+                SavePath::new("b".to_owned(), second.to_str().unwrap()).unwrap(),
+            ],
+            ..Default::default()
+        };
+
+        // A file can't be moved over a non-empty directory.
+        let storage_path = c.path().join("storage");
+        std::fs::create_dir_all(storage_path.join(&game.id).join("b").join("x")).unwrap();
+        Game::link(&game, &storage_path, false).unwrap_err();
+
+        assert!(std::fs::symlink_metadata(&first).unwrap().is_dir());
+        assert!(std::fs::symlink_metadata(&second).unwrap().is_file());
+        assert!(!storage_path.join(&game.id).join("a").exists());
+    }
+
+    #[test]
+    fn test_unlink_dir() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        std::fs::write(src.join("save"), "data").unwrap();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        Game::link(&game, &storage_path, false).unwrap();
+        Game::unlink(&game, &storage_path, false).unwrap();
+        assert!(std::fs::symlink_metadata(&src).unwrap().is_dir());
+        assert_eq!(std::fs::read_to_string(src.join("save")).unwrap(), "data");
+        assert!(!storage_path.join(&game.id).exists());
+    }
+
+    #[test]
+    fn test_unlink_file() {
+        let c = tempfile::tempdir().unwrap();
+        let src = c.path().join("save");
+        std::fs::write(&src, "data").unwrap();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        Game::link(&game, &storage_path, false).unwrap();
+        Game::unlink(&game, &storage_path, false).unwrap();
+        assert!(std::fs::symlink_metadata(&src).unwrap().is_file());
+        assert_eq!(std::fs::read_to_string(&src).unwrap(), "data");
+        assert!(!storage_path.join(&game.id).exists());
+    }
+
+    #[test]
+    fn test_unlink_unlinked() {
+        let c = tempfile::tempdir().unwrap();
+        let src = c.path().join("src");
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        std::fs::create_dir_all(storage_path.join(&game.id).join("saveid")).unwrap();
+        Game::unlink(&game, &storage_path, false).unwrap();
+        assert!(std::fs::symlink_metadata(&src).unwrap().is_dir());
+    }
+
+    #[test]
+    fn test_unlink_skips_absent_save() {
+        let c = tempfile::tempdir().unwrap();
+        let linked = c.path().join("linked");
+        std::fs::create_dir(&linked).unwrap();
+        let game = Game {
+            id: "gameid".to_owned(),
+            saves: vec![
+                SavePath::new("a".to_owned(), linked.to_str().unwrap()).unwrap(),
+                SavePath::new("b".to_owned(), c.path().join("absent").to_str().unwrap()).unwrap(),
+            ],
+            ..Default::default()
+        };
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        Game::link(&game, &storage_path, false).unwrap();
+        Game::unlink(&game, &storage_path, false).unwrap();
+        assert!(std::fs::symlink_metadata(&linked).unwrap().is_dir());
+        assert!(!storage_path.join(&game.id).exists());
+    }
+
+    #[test]
+    fn test_unlink_keeps_non_empty_storage() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        Game::link(&game, &storage_path, false).unwrap();
+        std::fs::write(storage_path.join(&game.id).join("other"), "").unwrap();
+        Game::unlink(&game, &storage_path, false).unwrap();
+        assert!(std::fs::symlink_metadata(&src).unwrap().is_dir());
+        assert!(storage_path.join(&game.id).join("other").exists());
+    }
+
+    #[test]
+    fn test_unlink_linked_elsewhere_fails() {
+        let c = tempfile::tempdir().unwrap();
+        let src = c.path().join("src");
+        let elsewhere = tempfile::tempdir().unwrap().into_path();
+        Linker::symlink(&src, &elsewhere).unwrap();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        std::fs::create_dir(storage_path.join(&game.id)).unwrap();
+        let err = Game::unlink(&game, &storage_path, false).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::AlreadyLinked(_)));
+        assert_eq!(Linker::link_target(&src).unwrap(), elsewhere);
+    }
+
+    #[test]
+    fn test_unlink_dry_run() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        Game::link(&game, &storage_path, false).unwrap();
+        Game::unlink(&game, &storage_path, true).unwrap();
+        assert!(Linker::link_target(&src).is_some());
+    }
+
+    #[test]
+    fn test_is_present_checks_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut save = SavePath::new("saveid".to_owned(), dir.path().to_str().unwrap()).unwrap();
+        assert!(save.is_present());
+
+        save.kind = Some(SaveKind::Directory);
+        assert!(save.is_present());
+
+        save.kind = Some(SaveKind::File);
+        assert!(!save.is_present());
+    }
+
+    #[test]
+    fn test_link_skips_wrong_kind() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let mut game = game_with_save(&src);
+        game.saves[0].kind = Some(SaveKind::File);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        let game_storage_path = storage_path.join(&game.id);
+        assert_eq!(
+            game.saves[0].locations(&game_storage_path).unwrap()[0].state(),
+            SaveState::Absent
+        );
+
+        Game::link(&game, &storage_path, false).unwrap();
+        assert!(std::fs::symlink_metadata(&src).unwrap().is_dir());
+        assert!(!game_storage_path.join("saveid").exists());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_update_paths_uses_proton_prefix() {
+        let root = tempfile::tempdir().unwrap();
+        let pfx = root.path().join("steamapps/compatdata/374320/pfx");
+        std::fs::create_dir_all(&pfx).unwrap();
+        let steam = crate::steam::Steam::load(root.path()).unwrap();
+
+        let mut save = SavePath::new("general".to_owned(), "/unused").unwrap();
+        save.os = vec![crate::game::Os::Windows];
+        save.path = "${APPDATA}\\DarkSoulsIII".to_owned();
+        let mut game = Game {
+            id: "darksoulsiii".to_owned(),
+            stores: Stores {
+                steam: Some(374_320),
+                ..Default::default()
+            },
+            saves: vec![save],
+            ..Default::default()
+        };
+
+        assert!(game.update_paths(Some(&steam), &[]).is_empty());
+        assert_eq!(
+            game.saves[0].expanded,
+            pfx.join("drive_c/users/steamuser/AppData/Roaming/DarkSoulsIII")
+        );
+
+        game.stores.steam = None;
+        assert_eq!(game.update_paths(Some(&steam), &[]).len(), 1);
+        assert!(game.saves.is_empty());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_update_paths_uses_wine_prefixes() {
+        let empty_prefix = tempfile::tempdir().unwrap().into_path();
+        let prefix = tempfile::tempdir().unwrap().into_path();
+        let user = prefix.join("drive_c/users/player");
+        std::fs::create_dir_all(user.join("Documents/My Games/Terraria")).unwrap();
+
+        let mut save = SavePath::new("general".to_owned(), "/unused").unwrap();
+        save.os = vec![crate::game::Os::Windows];
+        save.path = "%DOCUMENTS%\\My Games\\Terraria".to_owned();
+        let mut game = Game {
+            id: "terraria".to_owned(),
+            saves: vec![save],
+            ..Default::default()
+        };
+
+        assert!(game.update_paths(None, &[empty_prefix, prefix]).is_empty());
+        assert_eq!(
+            game.saves[0].expanded,
+            user.join("Documents/My Games/Terraria")
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_update_paths_prefers_present_saves() {
+        let prefix = tempfile::tempdir().unwrap().into_path();
+        let in_prefix = prefix.join("drive_c/users/player/AppData/Roaming/Game");
+        std::fs::create_dir_all(&in_prefix).unwrap();
+        let native = tempfile::tempdir().unwrap().into_path().join("Game");
+
+        let mut windows = SavePath::new("saves".to_owned(), "/unused").unwrap();
+        windows.os = vec![crate::game::Os::Windows];
+        windows.path = "${APPDATA}\\Game".to_owned();
+        let game = Game {
+            id: "game".to_owned(),
+            saves: vec![
+                windows,
+                SavePath::new("saves".to_owned(), native.to_str().unwrap()).unwrap(),
+            ],
+            ..Default::default()
+        };
+
+        let mut with_absent_native = game.clone();
+        let prefixes = vec![prefix];
+        let unavailable = with_absent_native.update_paths(None, &prefixes);
+        assert_eq!(unavailable.len(), 1);
+        assert_eq!(with_absent_native.saves[0].expanded, in_prefix);
+
+        std::fs::create_dir_all(&native).unwrap();
+        let mut with_present_native = game;
+        let unavailable = with_present_native.update_paths(None, &prefixes);
+        assert_eq!(unavailable.len(), 1);
+        assert_eq!(with_present_native.saves[0].expanded, native);
+    }
+
+    #[test]
+    fn test_glob_locations() {
+        let base = tempfile::tempdir().unwrap().into_path();
+        std::fs::create_dir_all(base.join("76561198000000001/profile")).unwrap();
+        std::fs::create_dir_all(base.join("76561198000000002/profile")).unwrap();
+        std::fs::create_dir_all(base.join("other/profile")).unwrap();
+        let game = game_with_save(&base.join("7656*").join("profile"));
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        let game_storage_path = storage_path.join(&game.id);
+        std::fs::create_dir_all(game_storage_path.join("saveid/76561198000000003/profile"))
+            .unwrap();
+
+        let locations = game.saves[0].locations(&game_storage_path).unwrap();
+        let ids: Vec<_> = locations.iter().map(|l| l.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "saveid/76561198000000001/profile",
+                "saveid/76561198000000002/profile",
+                "saveid/76561198000000003/profile"
+            ]
+        );
+        assert_eq!(
+            locations[2].path,
+            base.join("76561198000000003").join("profile")
+        );
+        assert_eq!(
+            locations[2].dest,
+            game_storage_path.join("saveid/76561198000000003/profile")
+        );
+    }
+
+    #[test]
+    fn test_glob_no_matches() {
+        let base = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&base.join("7656*").join("profile"));
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        assert!(game.saves[0].locations(&storage_path).unwrap().is_empty());
+        assert!(!game.saves[0].is_present());
+    }
+
+    #[test]
+    fn test_glob_link_restore_unlink() {
+        let base = tempfile::tempdir().unwrap().into_path();
+        let first = base.join("76561198000000001").join("profile");
+        let second = base.join("76561198000000002").join("profile");
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+        let game = game_with_save(&base.join("7656*").join("profile"));
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        let saves_storage = storage_path.join(&game.id).join("saveid");
+
+        Game::link(&game, &storage_path, false).unwrap();
+        assert_eq!(
+            Linker::link_target(&first).unwrap(),
+            saves_storage.join("76561198000000001").join("profile")
+        );
+        assert!(Linker::link_target(&second).is_some());
+
+        // Simulate a new machine.
+        std::fs::remove_dir_all(base.join("76561198000000001")).unwrap();
+        Linker::remove_link(&second).unwrap();
+        Game::restore(&game, &storage_path, false).unwrap();
+        assert!(Linker::link_target(&first).is_some());
+        assert!(Linker::link_target(&second).is_some());
+
+        Game::unlink(&game, &storage_path, false).unwrap();
+        assert!(std::fs::symlink_metadata(&first).unwrap().is_dir());
+        assert!(std::fs::symlink_metadata(&second).unwrap().is_dir());
+        assert!(!storage_path.join(&game.id).exists());
+    }
+
+    #[test]
+    fn test_filtered_link_and_unlink() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        std::fs::create_dir_all(src.join("slots")).unwrap();
+        std::fs::create_dir_all(src.join("backup")).unwrap();
+        std::fs::write(src.join("a.sav"), "a").unwrap();
+        std::fs::write(src.join("slots/b.sav"), "b").unwrap();
+        std::fs::write(src.join("backup/c.sav"), "c").unwrap();
+        std::fs::write(src.join("shader.cache"), "").unwrap();
+        let mut game = game_with_save(&src);
+        game.saves[0].include = vec!["*.sav".to_owned()];
+        game.saves[0].exclude = vec!["backup/*".to_owned()];
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        let dest = storage_path.join(&game.id).join("saveid");
+
+        Game::link(&game, &storage_path, false).unwrap();
+        assert!(std::fs::symlink_metadata(&src).unwrap().is_dir());
+        assert_eq!(
+            Linker::link_target(&src.join("a.sav")).unwrap(),
+            dest.join("a.sav")
+        );
+        assert_eq!(
+            Linker::link_target(&src.join("slots/b.sav")).unwrap(),
+            dest.join("slots/b.sav")
+        );
+        assert!(Linker::link_target(&src.join("backup/c.sav")).is_none());
+        assert!(Linker::link_target(&src.join("shader.cache")).is_none());
+        assert!(!dest.join("shader.cache").exists());
+
+        Game::unlink(&game, &storage_path, false).unwrap();
+        assert_eq!(std::fs::read_to_string(src.join("a.sav")).unwrap(), "a");
+        assert_eq!(
+            std::fs::read_to_string(src.join("slots/b.sav")).unwrap(),
+            "b"
+        );
+        assert!(Linker::link_target(&src.join("a.sav")).is_none());
+        assert!(!storage_path.join(&game.id).exists());
+    }
+
+    #[test]
+    fn test_filtered_invalid_pattern() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        std::fs::write(src.join("a.sav"), "a").unwrap();
+        let mut game = game_with_save(&src);
+        game.saves[0].include = vec!["[".to_owned()];
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+
+        let err = Game::link(&game, &storage_path, false).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidPattern(p, _) if p == "["));
+        assert!(Linker::link_target(&src).is_none());
+        assert!(Linker::link_target(&src.join("a.sav")).is_none());
+    }
+
+    #[test]
+    fn test_filtered_restore() {
+        let c = tempfile::tempdir().unwrap();
+        let src = c.path().join("src");
+        let mut game = game_with_save(&src);
+        game.saves[0].include = vec!["*.sav".to_owned()];
+        game.saves[0].exclude = vec!["backup/*".to_owned()];
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        let dest = storage_path.join(&game.id).join("saveid");
+        std::fs::create_dir_all(dest.join("slots")).unwrap();
+        std::fs::write(dest.join("slots/b.sav"), "b").unwrap();
+
+        Game::restore(&game, &storage_path, false).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(src.join("slots/b.sav")).unwrap(),
+            "b"
+        );
+    }
+
+    #[test]
+    fn test_state_absent() {
+        let c = tempfile::tempdir().unwrap();
+        let game = game_with_save(&c.path().join("src"));
+        let game_storage_path = c.path().join("storage").join(&game.id);
+        assert_eq!(
+            game.saves[0].locations(&game_storage_path).unwrap()[0].state(),
+            SaveState::Absent
+        );
+    }
+
+    #[test]
+    fn test_state_movable() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        let game_storage_path = storage_path.join(&game.id);
+        assert_eq!(
+            game.saves[0].locations(&game_storage_path).unwrap()[0].state(),
+            SaveState::Movable
+        );
+    }
+
+    #[test]
+    fn test_state_linked() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        Game::link(&game, &storage_path, false).unwrap();
+        let game_storage_path = storage_path.join(&game.id);
+        assert_eq!(
+            game.saves[0].locations(&game_storage_path).unwrap()[0].state(),
+            SaveState::Linked
+        );
+    }
+
+    #[test]
+    fn test_state_linked_elsewhere() {
+        let c = tempfile::tempdir().unwrap();
+        let src = c.path().join("src");
+        let elsewhere = tempfile::tempdir().unwrap().into_path();
+        Linker::symlink(&src, &elsewhere).unwrap();
+        let game = game_with_save(&src);
+        let game_storage_path = c.path().join("storage").join(&game.id);
+        assert_eq!(
+            game.saves[0].locations(&game_storage_path).unwrap()[0].state(),
+            SaveState::LinkedElsewhere(elsewhere)
+        );
+    }
+
+    #[test]
+    fn test_state_broken_link() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        Game::link(&game, &storage_path, false).unwrap();
+        let game_storage_path = storage_path.join(&game.id);
+        let dest = game_storage_path.join("saveid");
+        std::fs::remove_dir(&dest).unwrap();
+        assert_eq!(
+            game.saves[0].locations(&game_storage_path).unwrap()[0].state(),
+            SaveState::BrokenLink(dest)
+        );
+    }
+
+    #[test]
+    fn test_state_unlinked() {
+        let c = tempfile::tempdir().unwrap();
+        let game = game_with_save(&c.path().join("src"));
+        let game_storage_path = c.path().join("storage").join(&game.id);
+        std::fs::create_dir_all(game_storage_path.join("saveid")).unwrap();
+        assert_eq!(
+            game.saves[0].locations(&game_storage_path).unwrap()[0].state(),
+            SaveState::Unlinked
+        );
+    }
+
+    #[test]
+    fn test_state_conflict() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        let game_storage_path = storage_path.join(&game.id);
+        std::fs::create_dir_all(game_storage_path.join("saveid")).unwrap();
+        assert_eq!(
+            game.saves[0].locations(&game_storage_path).unwrap()[0].state(),
+            SaveState::Conflict
+        );
+    }
+
+    #[test]
+    fn test_is_linked() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        assert!(!game.is_linked(&storage_path).unwrap());
+        Game::link(&game, &storage_path, false).unwrap();
+        assert!(game.is_linked(&storage_path).unwrap());
+    }
+
+    #[test]
+    fn test_move_storage() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        std::fs::write(src.join("save"), "data").unwrap();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        Game::link(&game, &storage_path, false).unwrap();
+
+        game.move_storage(&storage_path, "renamed").unwrap();
+        assert!(!storage_path.join(&game.id).exists());
+        let dest = storage_path.join("renamed").join("saveid");
+        assert_eq!(Linker::link_target(&src), Some(dest));
+        assert_eq!(std::fs::read_to_string(src.join("save")).unwrap(), "data");
+    }
+
+    #[test]
+    fn test_move_storage_existing_destination() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        Game::link(&game, &storage_path, false).unwrap();
+        std::fs::create_dir(storage_path.join("renamed")).unwrap();
+
+        let err = game.move_storage(&storage_path, "renamed").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::SourceExists(_)));
+        assert!(game.is_linked(&storage_path).unwrap());
+    }
+
+    #[test]
+    fn test_preflight() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path();
+        assert!(Game::preflight(&[&game], &storage_path).is_empty());
+    }
+
+    #[test]
+    fn test_preflight_unwritable_storage_path() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path().join("missing");
+
+        let problems = Game::preflight(&[&game], &storage_path);
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(problems[0].kind(), ErrorKind::NotWritable(p) if *p == storage_path));
+        assert!(src.exists());
+    }
+
+    #[test]
+    fn test_link_runs_preflight() {
+        let src = tempfile::tempdir().unwrap().into_path();
+        let game = game_with_save(&src);
+        let storage_path = tempfile::tempdir().unwrap().into_path().join("missing");
+
+        let err = Game::link(&game, &storage_path, false).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::PreflightFailed(1)));
+        assert!(std::fs::symlink_metadata(&src).unwrap().is_dir());
+    }
+
+    #[test]
+    fn test_selection() {
+        let game = |id: &str, title: &str| Game {
+            id: id.to_owned(),
+            title: title.to_owned(),
+            ..Default::default()
+        };
+        let ds3 = game("darksoulsiii", "Dark Souls III");
+        let terraria = game("terraria", "Terraria");
+
+        let everything = Selection::default();
+        assert!(everything.includes(&ds3) && everything.includes(&terraria));
+
+        let ids = Selection {
+            ids: vec!["terraria".to_owned()],
+            ..Default::default()
+        };
+        assert!(!ids.includes(&ds3) && ids.includes(&terraria));
+        assert!(ids.names(&terraria));
+
+        let patterns = Selection {
+            patterns: vec![glob::Pattern::new("dark souls*").unwrap()],
+            excluded: vec!["terraria".to_owned()],
+            ..Default::default()
+        };
+        assert!(patterns.includes(&ds3) && !patterns.includes(&terraria));
+        assert!(!patterns.names(&ds3));
+
+        let excluded = Selection {
+            excluded: vec!["terraria".to_owned()],
+            ..Default::default()
+        };
+        assert!(excluded.includes(&ds3) && !excluded.includes(&terraria));
+    }
+
+    #[test]
+    fn test_run_all_report() {
+        let game = |id: &str| Game {
+            id: id.to_owned(),
+            ..Default::default()
+        };
+        let games = vec![
+            game("fails"),
+            game("ignored"),
+            game("idle"),
+            game("succeeds"),
+        ];
+        let actionable = vec![&games[0], &games[1], &games[3]];
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "storage_path": "/",
+            "ignored": ["ignored"]
+        }))
+        .unwrap();
+
+        let report =
+            Game::run_all(
+                &games,
+                &actionable,
+                &settings,
+                &Selection::default(),
+                |g| match g.id.as_str() {
+                    "fails" => Err(ErrorKind::SourceExists("/".into()).into()),
+                    _ => Ok(()),
+                },
+            );
+        assert_eq!(report.succeeded, vec!["succeeds"]);
+        assert_eq!(report.ignored, vec!["ignored"]);
+        assert_eq!(report.nothing_to_do, vec!["idle"]);
+        assert_eq!(report.failed.len(), 1);
+        assert!(matches!(
+            report.failed[0].1.kind(),
+            ErrorKind::SourceExists(_)
+        ));
+
+        let err = report.result().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::GamesFailed(1, 2)));
+    }
+
+    #[test]
+    fn test_run_all_named_ignored_game() {
+        let games = vec![Game {
+            id: "ignored".to_owned(),
+            ..Default::default()
+        }];
+        let actionable = vec![&games[0]];
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "storage_path": "/",
+            "ignored": ["ignored"]
+        }))
+        .unwrap();
+        let selection = Selection {
+            ids: vec!["ignored".to_owned()],
+            ..Default::default()
+        };
+
+        let report = Game::run_all(&games, &actionable, &settings, &selection, |_| Ok(()));
+        assert_eq!(report.succeeded, vec!["ignored"]);
+        assert!(report.result().is_ok());
+    }
 }
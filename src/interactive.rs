@@ -0,0 +1,159 @@
+use saveli::errors::*;
+use saveli::game::{Game, SaveKind, SavePath};
+use saveli::validate;
+use std::io::{BufRead, Write};
+
+/// Prompts for a new custom game's title, id and saves, describing what each
+/// save path expands to.
+pub fn prompt_for_game<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> Result<Game> {
+    let title = loop {
+        let title = ask(input, output, "Title")?;
+        if !title.is_empty() {
+            break title;
+        }
+        writeln!(output, "The title must not be empty")?;
+    };
+
+    let default_id = Game::id_from_title(&title);
+    let id = loop {
+        let id = ask(input, output, &format!("Id [{}]", default_id))?;
+        let id = if id.is_empty() {
+            default_id.clone()
+        } else {
+            id
+        };
+        if !id.is_empty() && validate::is_valid_id(&id) {
+            break id;
+        }
+        writeln!(
+            output,
+            "The id must only contain lowercase letters, digits, '-' and '_'"
+        )?;
+    };
+
+    let mut saves: Vec<SavePath> = Vec::new();
+    loop {
+        let save_id = ask(input, output, "Save id (leave empty to finish)")?;
+        if save_id.is_empty() {
+            if !saves.is_empty() {
+                break;
+            }
+            writeln!(output, "At least one save is required")?;
+            continue;
+        }
+
+        if !validate::is_valid_id(&save_id) {
+            writeln!(
+                output,
+                "The id must only contain lowercase letters, digits, '-' and '_'"
+            )?;
+            continue;
+        }
+
+        if saves.iter().any(|s| s.id == save_id) {
+            writeln!(output, "The game already has a save with id {}", save_id)?;
+            continue;
+        }
+
+        let save = loop {
+            let path = ask(input, output, "Path")?;
+            match SavePath::new(save_id.clone(), &path) {
+                Ok(mut save) => {
+                    save.detect_kind();
+                    describe(&save, output)?;
+                    break save;
+                }
+                Err(e) => writeln!(output, "{}", e)?,
+            }
+        };
+        saves.push(save);
+    }
+
+    Ok(Game {
+        title,
+        id,
+        custom: true,
+        saves,
+        ..Default::default()
+    })
+}
+
+/// Asks a yes or no question, defaulting to no.
+pub fn confirm<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+) -> Result<bool> {
+    let answer = ask(input, output, &format!("{} [y/N]", question))?;
+    Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+}
+
+fn ask<R: BufRead, W: Write>(input: &mut R, output: &mut W, question: &str) -> Result<String> {
+    write!(output, "{}: ", question)?;
+    output.flush()?;
+
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        bail!("Expected an answer to: {}", question);
+    }
+
+    Ok(line.trim().to_owned())
+}
+
+fn describe<W: Write>(save: &SavePath, output: &mut W) -> Result<()> {
+    let path = save.expanded.display();
+    match save.kind {
+        Some(SaveKind::File) => writeln!(output, "{} exists and is a file", path)?,
+        Some(SaveKind::Directory) => writeln!(output, "{} exists and is a directory", path)?,
+        None if save.is_present() => writeln!(output, "{} exists", path)?,
+        None => writeln!(output, "{} doesn't exist yet", path)?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_for_game() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("save.dat");
+        std::fs::write(&file, "").unwrap();
+
+        let answers = format!(
+            "\nDark Souls III\n\nNot Valid\nfile\n{}\nfile\ndir\nrelative\n{}\n\n",
+            file.display(),
+            dir.path().display()
+        );
+        let mut output = Vec::new();
+        let game = prompt_for_game(&mut answers.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(game.title, "Dark Souls III");
+        assert_eq!(game.id, "darksoulsiii");
+        assert!(game.custom);
+        assert_eq!(game.saves.len(), 2);
+        assert_eq!(game.saves[0].kind, Some(SaveKind::File));
+        assert_eq!(game.saves[1].kind, Some(SaveKind::Directory));
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("The title must not be empty"));
+        assert!(output.contains("The game already has a save with id file"));
+        assert!(output.contains("Found relative path"));
+        assert!(output.contains("exists and is a file"));
+    }
+
+    #[test]
+    fn test_prompt_for_game_ends_early() {
+        let mut output = Vec::new();
+        prompt_for_game(&mut "Title\n".as_bytes(), &mut output).unwrap_err();
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut output = Vec::new();
+        assert!(confirm(&mut "y\n".as_bytes(), &mut output, "Link").unwrap());
+        assert!(!confirm(&mut "\n".as_bytes(), &mut output, "Link").unwrap());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::errors::*;
+use crate::transaction::Step;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// A line in the journal. Each step is recorded as an intent before it's
+/// attempted, and marked as done once it has completed. Undoing a step while
+/// rolling back is recorded too, so that recovery can tell which steps are
+/// still in effect.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Record {
+    Begin {
+        game: String,
+    },
+    Intent(Step),
+    Done,
+    /// The step which was the nth to complete has been undone.
+    Undone(usize),
+}
+
+/// An append-only log of the changes a transaction is making, stored in the
+/// storage path so that an interrupted transaction can be recovered.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    pub fn path(storage_path: &Path) -> PathBuf {
+        storage_path.join("journal.json")
+    }
+
+    /// Starts a new journal for `game`. It fails if an unfinished journal
+    /// exists.
+    pub fn create(storage_path: &Path, game: &str) -> Result<Journal> {
+        let path = Journal::path(storage_path);
+        let file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                bail!(ErrorKind::UnfinishedJournal(path));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut journal = Journal { path, file };
+        journal.append(&Record::Begin {
+            game: game.to_owned(),
+        })?;
+        Ok(journal)
+    }
+
+    /// Opens an unfinished journal, if there is one, returning its records.
+    pub fn open(storage_path: &Path) -> Result<Option<(Journal, Vec<Record>)>> {
+        let path = Journal::path(storage_path);
+        let file = match OpenOptions::new().read(true).append(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut records = Vec::new();
+        for line in BufReader::new(&file).lines() {
+            let line = line?;
+            // A record which was only partially written can be ignored, as
+            // whatever it described hasn't been attempted yet.
+            match serde_json::from_str(&line) {
+                Ok(record) => records.push(record),
+                Err(_) => break,
+            }
+        }
+
+        Ok(Some((Journal { path, file }, records)))
+    }
+
+    /// Appends `record` and waits for it to reach the disk.
+    pub fn append(&mut self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Removes the journal once everything it describes has completed or been
+    /// undone.
+    pub fn finish(self) -> Result<()> {
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_create_twice_fails() {
+        let storage_path = tempdir().unwrap();
+        let _journal = Journal::create(storage_path.path(), "gameid").unwrap();
+        let err = Journal::create(storage_path.path(), "gameid").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UnfinishedJournal(_)));
+    }
+
+    #[test]
+    fn test_open_ignores_partial_record() {
+        let storage_path = tempdir().unwrap();
+        let mut journal = Journal::create(storage_path.path(), "gameid").unwrap();
+        journal
+            .append(&Record::Intent(Step::CreatedDir(PathBuf::from("/a"))))
+            .unwrap();
+        journal.file.write_all(b"{\"done").unwrap();
+
+        let (_, records) = Journal::open(storage_path.path()).unwrap().unwrap();
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn test_finish_removes_journal() {
+        let storage_path = tempdir().unwrap();
+        let journal = Journal::create(storage_path.path(), "gameid").unwrap();
+        journal.finish().unwrap();
+        assert!(Journal::open(storage_path.path()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,44 @@
+//! Moves game saves to a storage path, such as a synced drive, and creates
+//! links in their place.
+//!
+//! [`Database`] holds the games saveli knows about, loaded from the bundled,
+//! community and user database files in the storage path. [`Game::link`],
+//! [`Game::restore`] and [`Game::unlink`] operate on a single game, while
+//! [`Game::link_all`] and friends operate on a [`Selection`] of games and
+//! return a [`Report`] of what happened to each. Nothing is printed; progress
+//! is logged through the `log` crate and reported to the handler passed to
+//! [`events::set_handler`].
+
+#![recursion_limit = "128"]
+#[macro_use]
+extern crate error_chain;
+#[macro_use]
+extern crate log;
+
+pub mod database;
+pub mod errors;
+pub mod events;
+pub mod game;
+mod journal;
+pub mod linker;
+pub mod manifest;
+pub mod settings;
+pub mod steam;
+mod transaction;
+pub mod validate;
+pub mod variables;
+
+pub use database::Database;
+pub use errors::{Error, ErrorKind, Result};
+pub use game::{Game, Report, SavePath, Selection};
+pub use linker::Linker;
+pub use settings::Settings;
+
+use std::path::Path;
+
+/// Undoes the changes of an operation which was interrupted, e.g. by a crash,
+/// using the journal it left in the storage path. Returns whether there was
+/// anything to recover.
+pub fn recover(storage_path: &Path) -> Result<bool> {
+    transaction::Transaction::recover(storage_path)
+}
@@ -1,10 +1,9 @@
 use crate::errors::*;
-use fs_extra;
+use crate::events::{self, Event};
+use fs_extra::dir::TransitProcessResult;
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::Path;
-
-#[cfg(windows)]
-use tempfile;
+use std::path::{Path, PathBuf};
 
 pub struct Linker;
 
@@ -33,16 +32,14 @@ impl Linker {
         // because on Windows when src is a dir and dest is a file it returns
         // ErrorKind::PermissionDenied.
         if let Err(e) = Linker::os_symlink(from, to) {
-            if let Ok(md) = std::fs::symlink_metadata(from) {
-                if md.file_type().is_symlink() {
-                    if let Ok(target) = std::fs::read_link(from) {
-                        if target == to {
-                            return Ok(());
-                        }
-                        bail!(ErrorKind::AlreadyLinked(target));
-                    }
+            if let Some(target) = Linker::link_target(from) {
+                if target == to {
+                    return Ok(());
                 }
+                bail!(ErrorKind::AlreadyLinked(target));
+            }
 
+            if let Ok(md) = std::fs::symlink_metadata(from) {
                 if md.is_dir() || md.is_file() {
                     bail!(ErrorKind::SourceExists(from.to_path_buf()));
                 }
@@ -53,6 +50,36 @@ impl Linker {
         Ok(())
     }
 
+    /// Returns where the link at `path` points to, or `None` if `path` isn't a
+    /// link.
+    pub fn link_target(path: &Path) -> Option<PathBuf> {
+        match std::fs::symlink_metadata(path) {
+            Ok(md) if md.file_type().is_symlink() => std::fs::read_link(path).ok(),
+            _ => None,
+        }
+    }
+
+    /// Removes the link at `path`, leaving what it points to untouched.
+    pub fn remove_link(path: &Path) -> Result<()> {
+        if Linker::link_target(path).is_none() {
+            bail!("{} isn't a link", path.display());
+        }
+
+        // Directory links on Windows have to be removed as directories.
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::FileTypeExt;
+            if std::fs::symlink_metadata(path)?
+                .file_type()
+                .is_symlink_dir()
+            {
+                return Ok(std::fs::remove_dir(path)?);
+            }
+        }
+
+        Ok(std::fs::remove_file(path)?)
+    }
+
     /// This results in a call to CreateSymbolicLinkW
     #[cfg(windows)]
     fn os_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
@@ -71,24 +98,324 @@ impl Linker {
     pub fn move_item(src: &Path, dest: &Path) -> Result<u64> {
         // fs_extra doesn't attempt to rename files when possible:
         // https://github.com/webdesus/fs_extra/issues/20
-        if fs::rename(src, dest).is_ok() {
-            return Ok(0);
+        match fs::rename(src, dest) {
+            Ok(()) => {
+                debug!("Renamed {} to {}", src.display(), dest.display());
+                return Ok(0);
+            }
+            Err(e) => debug!(
+                "Couldn't rename {} to {}, copying it instead: {}",
+                src.display(),
+                dest.display(),
+                e
+            ),
         }
 
-        if src.is_dir() {
+        Linker::move_by_copying(src, dest)
+    }
+
+    /// Copies `src` to `dest`, and then removes `src` only if the checksums of
+    /// every copied file match those of the originals. If the copy fails or
+    /// they don't match, whatever was copied is removed instead.
+    fn move_by_copying(src: &Path, dest: &Path) -> Result<u64> {
+        let total = Linker::size_on_disk(src);
+        debug!(
+            "Copying {} files totalling {} bytes from {} to {}",
+            total.files,
+            total.bytes,
+            src.display(),
+            dest.display()
+        );
+
+        // Directories are copied inside `dest` when it already exists.
+        let copy = match src.file_name() {
+            Some(name) if src.is_dir() && dest.exists() => dest.join(name),
+            _ => dest.to_path_buf(),
+        };
+        // Checked up front so that cleaning up after a failure never removes
+        // something which was already there.
+        if fs::symlink_metadata(&copy).is_ok() {
+            bail!(ErrorKind::SourceExists(copy));
+        }
+
+        let before = Linker::checksums(src)?;
+
+        let mut progress = Progress::new(src, dest, total);
+        progress.report(0, 0);
+
+        let copied = if src.is_dir() {
             let mut options = fs_extra::dir::CopyOptions::new();
             options.copy_inside = true;
-            return fs_extra::dir::move_dir(src, dest, &options)
-                .chain_err(|| ErrorKind::FailedToMove(src.to_path_buf(), dest.to_path_buf()));
+            let mut files = 0;
+            fs_extra::dir::copy_with_progress(src, dest, &options, |p| {
+                if p.file_bytes_copied == p.file_total_bytes {
+                    files += 1;
+                }
+                progress.report(p.copied_bytes, files);
+                TransitProcessResult::ContinueOrAbort
+            })
+        } else {
+            let options = fs_extra::file::CopyOptions::new();
+            fs_extra::file::copy_with_progress(src, dest, &options, |p| {
+                let files = (p.copied_bytes == p.total_bytes) as u64;
+                progress.report(p.copied_bytes, files);
+            })
+        };
+
+        let copied = match copied {
+            Ok(copied) => copied,
+            Err(e) => {
+                Linker::remove_partial_copy(&copy);
+                return Err(e).chain_err(|| {
+                    ErrorKind::FailedToMove(src.to_path_buf(), dest.to_path_buf())
+                });
+            }
+        };
+        progress.report(total.bytes, total.files);
+
+        Linker::verify_copy(src, &before, &copy)?;
+        Linker::remove_item(src)?;
+
+        Ok(copied)
+    }
+
+    /// Checks the copy at `copy` against the checksums taken of `src` before
+    /// copying, removing the copy if it doesn't match.
+    fn verify_copy(src: &Path, before: &[(PathBuf, Vec<u8>)], copy: &Path) -> Result<()> {
+        let after = match Linker::checksums(copy) {
+            Ok(after) => after,
+            Err(e) => {
+                Linker::remove_partial_copy(copy);
+                return Err(e);
+            }
+        };
+
+        if let Some(relative) = Linker::find_mismatch(before, &after) {
+            Linker::remove_partial_copy(copy);
+            bail!(ErrorKind::ChecksumMismatch(src.join(relative)));
+        }
+        debug!(
+            "Verified the {} files copied to {}",
+            before.len(),
+            copy.display()
+        );
+
+        Ok(())
+    }
+
+    /// Removes a copy which failed, leaving the original untouched. Failing to
+    /// remove it is only logged, so that the reason the copy failed is the
+    /// error which is reported.
+    fn remove_partial_copy(copy: &Path) {
+        if fs::symlink_metadata(copy).is_err() {
+            return;
+        }
+
+        match Linker::remove_item(copy) {
+            Ok(()) => debug!("Removed the partial copy at {}", copy.display()),
+            Err(e) => warn!(
+                "Couldn't remove the partial copy at {}: {}",
+                copy.display(),
+                e
+            ),
+        }
+    }
+
+    /// Removes the file or directory at `path`, along with everything in it.
+    pub fn remove_item(path: &Path) -> Result<()> {
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// The SHA-256 checksum of every file at `path` by its path relative to
+    /// `path`, sorted by path. Links aren't followed.
+    fn checksums(path: &Path) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+        let mut checksums = Vec::new();
+        Linker::add_checksums(path, Path::new(""), &mut checksums)?;
+        checksums.sort();
+        Ok(checksums)
+    }
+
+    fn add_checksums(
+        path: &Path,
+        relative: &Path,
+        checksums: &mut Vec<(PathBuf, Vec<u8>)>,
+    ) -> Result<()> {
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                Linker::add_checksums(&entry.path(), &relative.join(entry.file_name()), checksums)?;
+            }
+        } else if metadata.is_file() {
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+            checksums.push((relative.to_path_buf(), hasher.finalize().to_vec()));
+        }
+
+        Ok(())
+    }
+
+    /// Whether every file at `original` has an identical copy at `copy`.
+    pub fn is_copy(copy: &Path, original: &Path) -> Result<bool> {
+        let before = Linker::checksums(original)?;
+        let after = Linker::checksums(copy)?;
+        Ok(Linker::find_mismatch(&before, &after).is_none())
+    }
+
+    /// The first file in `before` which is missing from `after` or differs.
+    fn find_mismatch<'a>(
+        before: &'a [(PathBuf, Vec<u8>)],
+        after: &[(PathBuf, Vec<u8>)],
+    ) -> Option<&'a Path> {
+        before
+            .iter()
+            .find(|file| !after.contains(file))
+            .map(|(path, _)| path.as_path())
+    }
+
+    /// The space available on the filesystem containing `path`.
+    pub fn available_space(path: &Path) -> Result<u64> {
+        Ok(fs2::available_space(path)?)
+    }
+
+    /// Whether `a` and `b` are on the same filesystem, in which case moving
+    /// from one to the other is a rename rather than a copy. When it can't be
+    /// determined they're assumed not to be.
+    pub fn same_filesystem(a: &Path, b: &Path) -> bool {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            match (fs::symlink_metadata(a), fs::metadata(b)) {
+                (Ok(a), Ok(b)) => a.dev() == b.dev(),
+                _ => false,
+            }
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = (a, b);
+            false
+        }
+    }
+
+    /// Whether files can be created in the directory.
+    pub fn is_writable(dir: &Path) -> bool {
+        tempfile::tempfile_in(dir).is_ok()
+    }
+
+    /// The total size and number of files at `path`, without following links.
+    pub fn size_on_disk(path: &Path) -> Size {
+        let metadata = match std::fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Size::default(),
+        };
+
+        if !metadata.is_dir() {
+            return Size {
+                bytes: metadata.len(),
+                files: 1,
+            };
         }
 
-        let options = fs_extra::file::CopyOptions::new();
-        fs_extra::file::move_file(src, dest, &options)
-            .chain_err(|| ErrorKind::FailedToMove(src.to_path_buf(), dest.to_path_buf()))
+        let mut size = Size::default();
+        if let Ok(entries) = std::fs::read_dir(path) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let entry_size = Linker::size_on_disk(&entry.path());
+                size.bytes += entry_size.bytes;
+                size.files += entry_size.files;
+            }
+        }
+
+        size
+    }
+
+    /// Completes a move which was interrupted after `dest` was partially
+    /// written, by copying everything from `src` over `dest` and then removing
+    /// `src` if the checksums of the copies match. If they don't, both are
+    /// left in place so that it can be tried again.
+    pub fn finish_move(src: &Path, dest: &Path) -> Result<()> {
+        let before = Linker::checksums(src)?;
+
+        let copied = if src.is_dir() {
+            let items = fs::read_dir(src)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            let mut options = fs_extra::dir::CopyOptions::new();
+            options.overwrite = true;
+            fs_extra::copy_items(&items, dest, &options)
+        } else {
+            let mut options = fs_extra::file::CopyOptions::new();
+            options.overwrite = true;
+            fs_extra::file::copy(src, dest, &options)
+        };
+        copied.chain_err(|| ErrorKind::FailedToMove(src.to_path_buf(), dest.to_path_buf()))?;
+
+        let after = Linker::checksums(dest)?;
+        if let Some(relative) = Linker::find_mismatch(&before, &after) {
+            bail!(ErrorKind::ChecksumMismatch(src.join(relative)));
+        }
+
+        if src.is_dir() {
+            fs::remove_dir_all(src)?;
+        } else {
+            fs::remove_file(src)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Size {
+    pub bytes: u64,
+    pub files: u64,
+}
+
+/// Reports the progress of copying a save to another filesystem as events,
+/// whenever another file or percent has been copied.
+struct Progress<'a> {
+    src: &'a Path,
+    dest: &'a Path,
+    total: Size,
+    last: Option<(u64, u64)>,
+}
+
+impl<'a> Progress<'a> {
+    fn new(src: &'a Path, dest: &'a Path, total: Size) -> Progress<'a> {
+        Progress {
+            src,
+            dest,
+            total,
+            last: None,
+        }
+    }
+
+    fn report(&mut self, bytes: u64, files: u64) {
+        let percent = (bytes * 100).checked_div(self.total.bytes).unwrap_or(100);
+        if self.last == Some((percent, files)) {
+            return;
+        }
+        self.last = Some((percent, files));
+
+        events::emit(&Event::Progress {
+            from: self.src,
+            to: self.dest,
+            copied_bytes: bytes,
+            total_bytes: self.total.bytes,
+            copied_files: files,
+            total_files: self.total.files,
+        });
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::match_like_matches_macro)]
 mod test {
     use super::*;
     use tempfile::{tempdir, NamedTempFile};
@@ -188,5 +515,135 @@ mod test {
         });
     }
 
-    // TODO: test move_item
+    #[test]
+    fn finish_move_partial_dir() {
+        let c = tempdir().unwrap();
+        let src = c.path().join("src");
+        let dest = c.path().join("dest");
+        fs::create_dir_all(src.join("a")).unwrap();
+        fs::write(src.join("a").join("b"), "b").unwrap();
+        fs::write(src.join("c"), "c").unwrap();
+        fs::create_dir_all(dest.join("a")).unwrap();
+        fs::write(dest.join("a").join("b"), "partial").unwrap();
+
+        Linker::finish_move(&src, &dest).unwrap();
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dest.join("a").join("b")).unwrap(), "b");
+        assert_eq!(fs::read_to_string(dest.join("c")).unwrap(), "c");
+    }
+
+    #[test]
+    fn move_by_copying_dir_and_file() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        std::fs::create_dir_all(src.join("a")).unwrap();
+        std::fs::write(src.join("a").join("b"), [1; 10]).unwrap();
+        std::fs::write(src.join("c"), [2; 5]).unwrap();
+        let before = Linker::checksums(&src).unwrap();
+
+        let dest = dir.path().join("dest");
+        Linker::move_by_copying(&src, &dest).unwrap();
+        assert!(!src.exists());
+        assert_eq!(Linker::checksums(&dest).unwrap(), before);
+
+        let file = dir.path().join("file");
+        Linker::move_by_copying(&dest.join("c"), &file).unwrap();
+        assert!(!dest.join("c").exists());
+        assert_eq!(std::fs::read(&file).unwrap(), [2; 5]);
+    }
+
+    #[test]
+    fn move_by_copying_removes_failed_copy() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        std::fs::create_dir(&src).unwrap();
+        std::fs::write(src.join("a"), "a").unwrap();
+        // Copying a dangling link fails, after the destination was created.
+        Linker::os_symlink(&src.join("b"), &dir.path().join("missing")).unwrap();
+
+        let dest = dir.path().join("dest");
+        let err = Linker::move_by_copying(&src, &dest).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::FailedToMove(..)));
+        assert!(!dest.exists());
+        assert_eq!(std::fs::read_to_string(src.join("a")).unwrap(), "a");
+    }
+
+    #[test]
+    fn verify_copy_removes_mismatched_copy() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let copy = dir.path().join("copy");
+        std::fs::create_dir(&src).unwrap();
+        std::fs::write(src.join("a"), "a").unwrap();
+        let before = Linker::checksums(&src).unwrap();
+        std::fs::create_dir(&copy).unwrap();
+        std::fs::write(copy.join("a"), "corrupt").unwrap();
+
+        let err = Linker::verify_copy(&src, &before, &copy).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ChecksumMismatch(path) if *path == src.join("a")));
+        assert!(!copy.exists());
+        assert!(src.join("a").exists());
+
+        std::fs::create_dir(&copy).unwrap();
+        std::fs::write(copy.join("a"), "a").unwrap();
+        Linker::verify_copy(&src, &before, &copy).unwrap();
+        assert!(copy.join("a").exists());
+    }
+
+    #[test]
+    fn move_by_copying_keeps_existing_dest() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dest = dir.path().join("dest");
+        std::fs::write(&src, "src").unwrap();
+        std::fs::write(&dest, "dest").unwrap();
+
+        let err = Linker::move_by_copying(&src, &dest).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::SourceExists(_)));
+        assert_eq!(std::fs::read_to_string(&src).unwrap(), "src");
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "dest");
+    }
+
+    #[test]
+    fn find_mismatch_changed_and_missing() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a"), "a").unwrap();
+        std::fs::write(dir.path().join("b"), "b").unwrap();
+        let before = Linker::checksums(dir.path()).unwrap();
+        assert_eq!(before.len(), 2);
+        assert_eq!(Linker::find_mismatch(&before, &before), None);
+
+        std::fs::write(dir.path().join("b"), "changed").unwrap();
+        let changed = Linker::checksums(dir.path()).unwrap();
+        assert_eq!(
+            Linker::find_mismatch(&before, &changed),
+            Some(Path::new("b"))
+        );
+
+        std::fs::remove_file(dir.path().join("a")).unwrap();
+        let missing = Linker::checksums(dir.path()).unwrap();
+        assert_eq!(
+            Linker::find_mismatch(&before, &missing),
+            Some(Path::new("a"))
+        );
+    }
+
+    #[test]
+    fn size_on_disk_dir_and_missing() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("a")).unwrap();
+        std::fs::write(dir.path().join("a").join("b"), [0; 10]).unwrap();
+        std::fs::write(dir.path().join("c"), [0; 5]).unwrap();
+        assert_eq!(
+            Linker::size_on_disk(dir.path()),
+            Size {
+                bytes: 15,
+                files: 2
+            }
+        );
+        assert_eq!(
+            Linker::size_on_disk(&dir.path().join("missing")),
+            Size::default()
+        );
+    }
 }
@@ -0,0 +1,98 @@
+use crate::output;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use saveli::errors::*;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Prints what saveli is doing at the chosen level, and optionally writes it
+/// to a file in more detail.
+struct Logger {
+    level: LevelFilter,
+    file: Option<Mutex<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= self.level {
+            // Keep standard output for the JSON when printing JSON.
+            if record.level() <= Level::Warn || output::is_json() {
+                eprintln!("{}", record.args());
+            } else {
+                println!("{}", record.args());
+            }
+        }
+
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = writeln!(file, "{:5} {}", record.level(), record.args());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// The level for `-q`, nothing, `-v` and `-vv` respectively.
+pub fn level(quiet: bool, verbosity: u64) -> LevelFilter {
+    match (quiet, verbosity) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    }
+}
+
+/// Installs the logger. The log file, if there is one, is appended to and
+/// receives everything at the debug level or above regardless of `level`.
+pub fn init(level: LevelFilter, log_file: Option<&Path>) -> Result<()> {
+    let file = match log_file {
+        Some(path) => Some(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .chain_err(|| format!("Failed to open the log file {}", path.display()))?,
+        ),
+        None => None,
+    };
+
+    let max_level = if file.is_some() {
+        level.max(LevelFilter::Debug)
+    } else {
+        level
+    };
+
+    let logger = Logger {
+        level,
+        file: file.map(Mutex::new),
+    };
+
+    log::set_boxed_logger(Box::new(logger)).chain_err(|| "The logger was already installed")?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level() {
+        assert_eq!(level(false, 0), LevelFilter::Info);
+        assert_eq!(level(false, 1), LevelFilter::Debug);
+        assert_eq!(level(false, 2), LevelFilter::Trace);
+        assert_eq!(level(true, 2), LevelFilter::Warn);
+    }
+}
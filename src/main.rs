@@ -1,40 +1,114 @@
-#![recursion_limit = "128"]
 #[macro_use]
 extern crate error_chain;
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+mod output;
 
-mod database;
-mod errors;
-mod game;
-mod linker;
-mod settings;
+mod interactive;
+mod logger;
 
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
-use database::Database;
-use errors::*;
-use game::Game;
-use settings::Settings;
+use output::Done;
+use saveli::errors::*;
+use saveli::events::{self, Event};
+use saveli::game::ListFilter;
+use saveli::{database, manifest, validate};
+use saveli::{Database, Game, SavePath, Selection, Settings};
+use serde::Serialize;
 use std::path::Path;
 
+/// The arguments for selecting which games link, restore and unlink apply to.
+fn selection_args() -> [Arg<'static, 'static>; 3] {
+    [
+        Arg::with_name("ids")
+            .index(1)
+            .multiple(true)
+            .help("The ids of the games, which are used even if they're ignored"),
+        Arg::with_name("match")
+            .long("match")
+            .short("m")
+            .value_name("pattern")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Only use games whose id or title matches the glob pattern"),
+        Arg::with_name("exclude")
+            .long("exclude")
+            .short("e")
+            .value_name("id")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Skip the game this time"),
+    ]
+}
+
 fn get_command_line_matches() -> ArgMatches<'static> {
     App::new("Saveli")
         .version(env!("CARGO_PKG_VERSION"))
-        .version_short("v")
         .author("Steven Joruk <steven@joruk.com>")
         .about("Moves game saves and creates links in their place.")
         .setting(AppSettings::ArgRequiredElseHelp)
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .value_name("format")
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .global(true)
+                .help("Print text, or JSON for other programs to read"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .long("quiet")
+                .short("q")
+                .global(true)
+                .conflicts_with("verbose")
+                .help("Only print warnings, errors and results"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .multiple(true)
+                .global(true)
+                .help("Print more details of what's being done, -vv for even more"),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .value_name("path")
+                .global(true)
+                .help("Append a detailed log to the file"),
+        )
         .setting(AppSettings::DisableHelpSubcommand)
         .subcommand(
             SubCommand::with_name("set-storage-path")
                 .about("Set where game saves and meta data should be stored")
                 .arg(Arg::with_name("path").index(1).required(true)),
         )
+        .subcommand(
+            SubCommand::with_name("add-wine-prefix")
+                .about(
+                    "Look for Windows game saves in a Wine prefix when not \
+                     running on Windows",
+                )
+                .arg(Arg::with_name("path").index(1).required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("remove-wine-prefix")
+                .about("The inverse of add-wine-prefix")
+                .arg(Arg::with_name("path").index(1).required(true)),
+        )
         .subcommand(
             SubCommand::with_name("link")
                 .about(
                     "Move game saves from their original locations to the \
                      storage path and create links to their new location",
                 )
-                .arg(Arg::with_name("dry-run").short("d").long("dry-run")),
+                .arg(Arg::with_name("dry-run").short("d").long("dry-run"))
+                .args(&selection_args()),
         )
         .subcommand(
             SubCommand::with_name("restore")
@@ -42,17 +116,64 @@ fn get_command_line_matches() -> ArgMatches<'static> {
                     "Creates links to game saves which have been moved to the \
                      storage path",
                 )
-                .arg(Arg::with_name("dry-run").short("d").long("dry-run")),
+                .arg(Arg::with_name("dry-run").short("d").long("dry-run"))
+                .args(&selection_args()),
         )
         .subcommand(
             SubCommand::with_name("unlink")
                 .about("The inverse of link")
-                .arg(Arg::with_name("dry-run").short("d").long("dry-run")),
+                .arg(Arg::with_name("dry-run").short("d").long("dry-run"))
+                .args(&selection_args()),
+        )
+        .subcommand(SubCommand::with_name("recover").about(
+            "Undo an operation which was interrupted, e.g. by a crash. \
+                     This is also done automatically before other commands",
+        ))
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("Show the state of every game save which saveli knows about"),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("List the games in the database")
+                .arg(
+                    Arg::with_name("present")
+                        .long("present")
+                        .help("Only list games with saves on this machine"),
+                )
+                .arg(
+                    Arg::with_name("linked")
+                        .long("linked")
+                        .help("Only list games with saves linked to the storage path"),
+                )
+                .arg(
+                    Arg::with_name("custom")
+                        .long("custom")
+                        .help("Only list custom games"),
+                )
+                .arg(
+                    Arg::with_name("ignored")
+                        .long("ignored")
+                        .help("Only list ignored games"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Show a game's details and the state of its saves")
+                .arg(Arg::with_name("id").index(1).required(true)),
         )
         .subcommand(
             SubCommand::with_name("search")
-                .about("Search the database for the keyword")
-                .arg(Arg::with_name("keyword").index(1).required(true)),
+                .about(
+                    "Search the database for the keyword, ignoring case and \
+                     punctuation",
+                )
+                .arg(Arg::with_name("keyword").index(1).required(true))
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the results as JSON"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("ignore")
@@ -70,15 +191,114 @@ fn get_command_line_matches() -> ArgMatches<'static> {
         .subcommand(
             SubCommand::with_name("add")
                 .about("Add a game to the database")
-                .arg(Arg::with_name("title").index(1).required(true))
-                .arg(Arg::with_name("id").index(2).required(true))
-                .arg(Arg::with_name("path").index(3).required(true)),
+                .arg(
+                    Arg::with_name("title")
+                        .index(1)
+                        .required_unless("interactive"),
+                )
+                .arg(Arg::with_name("id").index(2).required_unless("interactive"))
+                .arg(
+                    Arg::with_name("path")
+                        .index(3)
+                        .help("The path of a save with the id primary"),
+                )
+                .arg(
+                    Arg::with_name("save")
+                        .long("save")
+                        .short("s")
+                        .value_name("id=path")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Add a save, which can be a file or a directory"),
+                )
+                .arg(
+                    Arg::with_name("interactive")
+                        .long("interactive")
+                        .short("i")
+                        .conflicts_with_all(&["title", "id", "path", "save"])
+                        .help("Prompt for the game's details"),
+                )
+                .arg(
+                    Arg::with_name("link")
+                        .long("link")
+                        .short("l")
+                        .help("Link the game's saves once it has been added"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Remove a custom game from the database")
+                .arg(Arg::with_name("id").index(1).required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("edit")
+                .about(
+                    "Change a game's title or saves, creating a custom entry \
+                     for it if it isn't custom",
+                )
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .arg(Arg::with_name("id").index(1).required(true))
+                .arg(
+                    Arg::with_name("title")
+                        .long("title")
+                        .short("t")
+                        .takes_value(true)
+                        .help("Change the game's title"),
+                )
+                .arg(
+                    Arg::with_name("save")
+                        .long("save")
+                        .short("s")
+                        .value_name("id=path")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Add a save, or change the path of an existing one"),
+                )
+                .arg(
+                    Arg::with_name("remove-save")
+                        .long("remove-save")
+                        .short("r")
+                        .value_name("id")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Remove a save"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rename-id")
+                .about(
+                    "Change a custom game's id, moving its saves in the storage \
+                     path and updating the links to them",
+                )
+                .arg(Arg::with_name("id").index(1).required(true))
+                .arg(Arg::with_name("new-id").index(2).required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("import-manifest")
+                .about(
+                    "Import the games from a local copy of Ludusavi's \
+                     manifest, leaving custom entries untouched",
+                )
+                .arg(Arg::with_name("file").index(1).required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("db")
+                .about("Work with database files")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("validate")
+                        .about(
+                            "Check a database file for problems, or the \
+                             database files in the storage path",
+                        )
+                        .arg(Arg::with_name("file").index(1)),
+                ),
         )
         .get_matches()
 }
 
 fn set_storage_path(path: &Path, settings: &mut Settings) -> Result<()> {
-    if path.components().next() == None {
+    if path.components().next().is_none() {
         bail!("You must specify a path");
     }
 
@@ -96,24 +316,312 @@ fn set_storage_path(path: &Path, settings: &mut Settings) -> Result<()> {
 
     settings.save()?;
 
-    println!(
+    say!(
         "Your storage path has been set to {}",
         settings.storage_path.display()
     );
+    output::emit(&Done {
+        command: "set-storage-path",
+        result: serde_json::json!({ "storage_path": settings.storage_path }),
+    });
+
+    Ok(())
+}
+
+fn add_wine_prefix(path: &Path, settings: &mut Settings) -> Result<()> {
+    let path = std::env::current_dir()?.join(path);
+    if !path.join("drive_c").is_dir() {
+        bail!(
+            "{} doesn't look like a Wine prefix, it has no drive_c directory",
+            path.display()
+        );
+    }
+
+    settings.add_wine_prefix(path)
+}
+
+#[derive(Serialize)]
+struct SearchResult<'a> {
+    id: &'a str,
+    title: &'a str,
+    aliases: &'a [String],
+    present: bool,
+}
+
+fn search(db: &Database, keyword: &str, json: bool) -> Result<()> {
+    if keyword.trim().is_empty() {
+        bail!("The keyword must not be empty");
+    }
+
+    let results: Vec<SearchResult> = db
+        .search(keyword)
+        .into_iter()
+        .map(|game| SearchResult {
+            id: &game.id,
+            title: &game.title,
+            aliases: &game.aliases,
+            present: game.has_present_saves(),
+        })
+        .collect();
+
+    if output::is_json() {
+        output::emit(&Done {
+            command: "search",
+            result: serde_json::json!({ "games": results }),
+        });
+        return Ok(());
+    }
+
+    if json {
+        say!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+
+    if results.is_empty() {
+        say!("Couldn't find any matching games");
+    }
+
+    for result in results {
+        let present = if result.present {
+            ", its saves are present"
+        } else {
+            ""
+        };
+        say!("Found {} ({}){}", result.title, result.id, present);
+    }
+
+    Ok(())
+}
+
+/// Parses saves specified as `<id>=<path>`.
+fn parse_saves<T: AsRef<str>>(saves: &[T]) -> Result<Vec<SavePath>> {
+    let mut save_paths: Vec<SavePath> = Vec::new();
+
+    for save in saves {
+        let (save_id, path) = match save.as_ref().split_once('=') {
+            Some(split) => split,
+            None => bail!("Saves must be specified as <id>=<path>: {}", save.as_ref()),
+        };
+
+        if save_id.is_empty() || !validate::is_valid_id(save_id) {
+            bail!(
+                "The save id {:?} must only contain lowercase letters, digits, '-' and '_'",
+                save_id
+            );
+        }
+        if save_paths.iter().any(|s| s.id == save_id) {
+            bail!("The save id {} is used more than once", save_id);
+        }
+
+        let mut save = SavePath::new(save_id.to_owned(), path)?;
+        save.detect_kind();
+        save_paths.push(save);
+    }
+
+    Ok(save_paths)
+}
+
+fn edit(db: &mut Database, settings: &Settings, matches: &ArgMatches) -> Result<()> {
+    let id = matches.value_of("id").unwrap();
+    let saves: Vec<&str> = matches.values_of("save").into_iter().flatten().collect();
+    let edit = database::Edit {
+        title: matches.value_of("title").map(str::to_owned),
+        saves: parse_saves(&saves)?,
+        removed_saves: matches
+            .values_of("remove-save")
+            .into_iter()
+            .flatten()
+            .map(str::to_owned)
+            .collect(),
+    };
+
+    if let Some(game) = db.games.iter().find(|g| g.id == id) {
+        let changed = edit.saves.iter().map(|s| s.id.as_str());
+        for save_id in changed.chain(edit.removed_saves.iter().map(String::as_str)) {
+            if game.save_is_linked(&settings.storage_path, save_id)? {
+                bail!(
+                    "{}'s {} save is linked, unlink it before changing it",
+                    game.title,
+                    save_id
+                );
+            }
+        }
+    }
+
+    info!("Editing {}", id);
+    db.edit(id, &edit)?;
+    output::emit(&Done {
+        command: "edit",
+        result: serde_json::json!({ "game": id }),
+    });
+
+    Ok(())
+}
+
+fn selection(db: &Database, matches: &ArgMatches) -> Result<Selection> {
+    let values = |name| -> Vec<String> {
+        matches
+            .values_of(name)
+            .into_iter()
+            .flatten()
+            .map(str::to_owned)
+            .collect()
+    };
+
+    let selection = Selection {
+        ids: values("ids"),
+        patterns: values("match")
+            .iter()
+            .map(|p| glob::Pattern::new(p).chain_err(|| format!("Invalid pattern {}", p)))
+            .collect::<Result<_>>()?,
+        excluded: values("exclude"),
+    };
+
+    for id in selection.ids.iter().chain(selection.excluded.iter()) {
+        if !db.games.iter().any(|g| g.id == *id) {
+            bail!("Couldn't find a game with id {}", id);
+        }
+    }
+
+    Ok(selection)
+}
+
+fn add(db: &mut Database, settings: &Settings, matches: &ArgMatches) -> Result<()> {
+    let mut link = matches.is_present("link");
+
+    let game = if matches.is_present("interactive") {
+        if output::is_json() {
+            bail!("Games can't be added interactively when printing JSON");
+        }
+
+        let stdin = std::io::stdin();
+        let mut input = stdin.lock();
+        let mut output = std::io::stdout();
+        let game = interactive::prompt_for_game(&mut input, &mut output)?;
+        if !link {
+            link = interactive::confirm(&mut input, &mut output, "Link its saves now?")?;
+        }
+        game
+    } else {
+        let id = matches.value_of("id").unwrap();
+        if id.is_empty() || !validate::is_valid_id(id) {
+            bail!("The id must only contain lowercase letters, digits, '-' and '_'");
+        }
+
+        let mut saves = Vec::new();
+        if let Some(path) = matches.value_of("path") {
+            saves.push(format!("primary={}", path));
+        }
+        saves.extend(
+            matches
+                .values_of("save")
+                .into_iter()
+                .flatten()
+                .map(str::to_owned),
+        );
+
+        if saves.is_empty() {
+            bail!("You must specify at least one save");
+        }
+
+        let save_paths = parse_saves(&saves)?;
+
+        Game {
+            id: id.to_owned(),
+            title: matches.value_of("title").unwrap().to_owned(),
+            custom: true,
+            saves: save_paths,
+            ..Default::default()
+        }
+    };
+
+    info!("Adding {}", game.title);
+    db.add(game.clone())?;
+
+    if link {
+        game.link(&settings.storage_path, settings.dry_run)?;
+    }
+
+    output::emit(&Done {
+        command: "add",
+        result: serde_json::json!({ "game": game.id, "linked": link }),
+    });
 
     Ok(())
 }
 
+/// Checks the database files, printing every problem found. Fails if any
+/// were found.
+fn validate_files(paths: &[impl AsRef<Path>]) -> Result<()> {
+    let mut problems = 0;
+    let mut files = Vec::new();
+
+    for path in paths {
+        let path = path.as_ref();
+        let findings = validate::validate(&std::fs::read_to_string(path)?);
+        for finding in &findings {
+            say!(
+                "{}#{}: {}",
+                path.display(),
+                finding.pointer,
+                finding.message
+            );
+        }
+
+        if findings.is_empty() {
+            say!("{} is valid", path.display());
+        }
+
+        problems += findings.len();
+        files.push(serde_json::json!({ "path": path, "findings": findings }));
+    }
+
+    output::emit(&Done {
+        command: "db validate",
+        result: serde_json::json!({ "files": files }),
+    });
+
+    if problems > 0 {
+        bail!(ErrorKind::InvalidDatabase(problems));
+    }
+
+    Ok(())
+}
+
+/// Reports that the game doesn't exist without failing the command.
+fn game_not_found(id: &str) {
+    let message = format!("Couldn't find a game with id {}", id);
+    error!("{}", message);
+    output::emit(&Event::error(Some(id), &message.into()));
+}
+
 fn run() -> Result<()> {
+    let matches = get_command_line_matches();
+    output::set_json(matches.value_of("output") == Some("json"));
+    logger::init(
+        logger::level(
+            matches.is_present("quiet"),
+            matches.occurrences_of("verbose"),
+        ),
+        matches.value_of("log-file").map(Path::new),
+    )?;
+    let quiet = matches.is_present("quiet");
+    events::set_handler(move |event| match event {
+        Event::Progress { .. } if !output::is_json() => {
+            if !quiet {
+                output::show_progress(event);
+            }
+        }
+        _ => output::emit(event),
+    });
+
     let mut settings = match Settings::load() {
         Err(err) => {
-            eprintln!("{}", err);
+            warn!("Couldn't load the settings: {}", err);
             Settings::default()
         }
         Ok(s) => s,
     };
-
-    let matches = get_command_line_matches();
     let (sub_name, sub_matches) = match matches.subcommand() {
         (n, Some(m)) => (n, m),
         _ => unreachable!(),
@@ -124,7 +632,32 @@ fn run() -> Result<()> {
         return set_storage_path(Path::new(path_str), &mut settings);
     }
 
-    if settings.storage_path.components().next() == None {
+    match sub_name {
+        "add-wine-prefix" | "remove-wine-prefix" => {
+            let path = Path::new(sub_matches.value_of("path").unwrap());
+            if sub_name == "add-wine-prefix" {
+                add_wine_prefix(path, &mut settings)?;
+            } else {
+                settings.remove_wine_prefix(&std::env::current_dir()?.join(path))?;
+            }
+            output::emit(&Done {
+                command: sub_name,
+                result: serde_json::json!({ "wine_prefixes": settings.wine_prefixes }),
+            });
+            return Ok(());
+        }
+        "db" => {
+            if let Some(file) = sub_matches
+                .subcommand_matches("validate")
+                .and_then(|m| m.value_of("file"))
+            {
+                return validate_files(&[file]);
+            }
+        }
+        _ => (),
+    }
+
+    if settings.storage_path.components().next().is_none() {
         bail!("You must set the storage path.")
     }
 
@@ -135,17 +668,61 @@ fn run() -> Result<()> {
         );
     }
 
-    let mut db = Database::new(&settings.storage_path)?;
+    let recovered = saveli::recover(&settings.storage_path)?;
+    if sub_name == "recover" {
+        if !recovered {
+            say!("There's nothing to recover");
+        }
+        output::emit(&Done {
+            command: "recover",
+            result: serde_json::json!({ "recovered": recovered }),
+        });
+        return Ok(());
+    }
+
+    if sub_name == "db" {
+        let files = Database::files(&settings.storage_path)?;
+        if files.is_empty() {
+            warn!("There are no database files in the storage path");
+        }
+        return validate_files(&files);
+    }
+
+    let mut db = Database::new(&settings.storage_path, &settings.wine_prefixes)?;
 
     settings.dry_run = sub_matches.is_present("dry-run");
 
     match sub_name {
-        "link" => Game::link_all(&db, &settings)?,
-        "restore" => Game::restore_all(&db, &settings)?,
-        "unlink" => Game::unlink_all(&db, &settings)?,
+        "link" | "restore" | "unlink" => {
+            let selection = selection(&db, sub_matches)?;
+            let report = match sub_name {
+                "link" => Game::link_all(&db, &settings, &selection)?,
+                "restore" => Game::restore_all(&db, &settings, &selection)?,
+                _ => Game::unlink_all(&db, &settings, &selection)?,
+            };
+            output::print_summary(&report, sub_name);
+            report.result()?;
+        }
+        "status" => output::print_status(&Game::status_all(&db, &settings)?, &settings),
+        "list" => {
+            let filter = ListFilter {
+                present: sub_matches.is_present("present"),
+                linked: sub_matches.is_present("linked"),
+                custom: sub_matches.is_present("custom"),
+                ignored: sub_matches.is_present("ignored"),
+            };
+            output::print_list(&Game::list_all(&db, &settings, &filter)?);
+        }
+        "info" => {
+            let id = sub_matches.value_of("id").unwrap();
+            match db.games.iter().find(|g| g.id == id) {
+                Some(g) => output::print_info(g, &settings)?,
+                None => bail!("Couldn't find a game with id {}", id),
+            }
+        }
         "search" => {
             let keyword = sub_matches.value_of("keyword").unwrap();
-            db.search(&keyword);
+            search(&db, keyword, sub_matches.is_present("json"))?;
         }
         "ignore" => {
             let id = sub_matches.value_of("id").unwrap();
@@ -154,8 +731,14 @@ fn run() -> Result<()> {
             }
 
             match db.games.iter().find(|g| g.id == id) {
-                Some(g) => settings.ignore_game(&g)?,
-                None => eprintln!("Couldn't find a game with id {}", id),
+                Some(g) => {
+                    settings.ignore_game(g)?;
+                    output::emit(&Done {
+                        command: "ignore",
+                        result: serde_json::json!({ "game": id }),
+                    });
+                }
+                None => game_not_found(id),
             }
         }
         "heed" => {
@@ -165,22 +748,62 @@ fn run() -> Result<()> {
             }
 
             match db.games.iter().find(|g| g.id == id) {
-                Some(g) => settings.heed_game(&g)?,
-                None => eprintln!("Couldn't find a game with id {}", id),
+                Some(g) => {
+                    settings.heed_game(g)?;
+                    output::emit(&Done {
+                        command: "heed",
+                        result: serde_json::json!({ "game": id }),
+                    });
+                }
+                None => game_not_found(id),
             }
         }
-        "add" => {
-            let game = Game {
-                id: sub_matches.value_of("id").unwrap().to_owned(),
-                title: sub_matches.value_of("title").unwrap().to_owned(),
-                custom: true,
-                saves: vec![game::SavePath::new(
-                    "primary".to_owned(),
-                    sub_matches.value_of("path").unwrap().to_owned(),
-                )?],
-            };
-            println!("Adding {}", game.title);
-            db.add(game)?;
+        "add" => add(&mut db, &settings, sub_matches)?,
+        "remove" => {
+            let id = sub_matches.value_of("id").unwrap();
+            let title = db.custom_game(id)?.title.clone();
+            info!("Removing {}", title);
+            db.remove(id, &settings.storage_path)?;
+
+            let storage = settings.storage_path.join(id);
+            let stored = storage.exists();
+            if stored {
+                info!("Its saves are still in {}", storage.display());
+            }
+            output::emit(&Done {
+                command: "remove",
+                result: serde_json::json!({
+                    "game": id,
+                    "storage": if stored { Some(&storage) } else { None },
+                }),
+            });
+        }
+        "edit" => edit(&mut db, &settings, sub_matches)?,
+        "rename-id" => {
+            let id = sub_matches.value_of("id").unwrap();
+            let new_id = sub_matches.value_of("new-id").unwrap();
+            if new_id.is_empty() || !validate::is_valid_id(new_id) {
+                bail!("The id must only contain lowercase letters, digits, '-' and '_'");
+            }
+
+            info!("Renaming {} to {}", id, new_id);
+            db.rename_id(id, new_id, &settings.storage_path)?;
+            settings.rename_ignored_game(id, new_id)?;
+            output::emit(&Done {
+                command: "rename-id",
+                result: serde_json::json!({ "game": id, "new_id": new_id }),
+            });
+        }
+        "import-manifest" => {
+            let file = Path::new(sub_matches.value_of("file").unwrap());
+            let games = manifest::load(file)?;
+            info!("Importing {} games from {}", games.len(), file.display());
+            let imported = db.import("ludusavi", games, &settings.storage_path)?;
+            say!("Imported {} games", imported);
+            output::emit(&Done {
+                command: "import-manifest",
+                result: serde_json::json!({ "imported": imported }),
+            });
         }
         _ => unreachable!(),
     }
@@ -188,9 +811,23 @@ fn run() -> Result<()> {
     Ok(())
 }
 
+/// 2 means some games failed and 3 that every game which was attempted
+/// failed. Other errors exit with 1.
+fn exit_code(err: &Error) -> i32 {
+    match err.kind() {
+        ErrorKind::GamesFailed(failed, attempted) if failed == attempted => 3,
+        ErrorKind::GamesFailed(..) => 2,
+        _ => 1,
+    }
+}
+
 fn main() {
     if let Err(err) = run() {
-        eprintln!("{}", err);
-        std::process::exit(1);
+        if output::is_json() {
+            output::emit(&Event::error(None, &err));
+        } else {
+            eprintln!("{}", err);
+        }
+        std::process::exit(exit_code(&err));
     }
 }
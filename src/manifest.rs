@@ -0,0 +1,395 @@
+use crate::errors::*;
+use crate::game::{Game, Os, SavePath, Stores};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// An entry in Ludusavi's manifest, see
+/// https://github.com/mtkennerly/ludusavi-manifest. Only the parts saveli can
+/// use are parsed.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ManifestGame {
+    files: BTreeMap<String, ManifestFile>,
+    install_dir: BTreeMap<String, serde_yaml::Value>,
+    steam: Option<ManifestStore<u32>>,
+    gog: Option<ManifestStore<u64>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ManifestFile {
+    tags: Vec<String>,
+    when: Vec<ManifestConstraint>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ManifestConstraint {
+    os: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ManifestStore<T> {
+    id: Option<T>,
+}
+
+/// Reads a local copy of Ludusavi's manifest, converting the entries which
+/// can be represented by saveli in to games.
+pub fn load(path: &Path) -> Result<Vec<Game>> {
+    let data = std::fs::read_to_string(path)?;
+    parse(&data)
+}
+
+fn parse(data: &str) -> Result<Vec<Game>> {
+    let manifest: BTreeMap<String, ManifestGame> = serde_yaml::from_str(data)?;
+    let mut games: Vec<Game> = Vec::new();
+
+    for (title, entry) in manifest {
+        let steam_app_id = entry.steam.as_ref().and_then(|s| s.id);
+        // The install directory can only be found through Steam.
+        let install_dir = match (steam_app_id, entry.install_dir.keys().next()) {
+            (Some(_), Some(dir)) if entry.install_dir.len() == 1 => Some(dir.as_str()),
+            _ => None,
+        };
+
+        let mut saves: Vec<SavePath> = Vec::new();
+        for (path, file) in &entry.files {
+            let is_save = file.tags.is_empty() || file.tags.iter().any(|t| t == "save");
+            if !is_save {
+                continue;
+            }
+
+            let translated: Vec<(String, Vec<Os>)> = os_variants(path, file_os(path, file))
+                .into_iter()
+                .filter_map(|os| Some((translate(path, &os, steam_app_id, install_dir)?, os)))
+                .collect();
+            if translated.is_empty() {
+                continue;
+            }
+
+            let mut id = save_id(path);
+            let mut suffix = 2;
+            while saves.iter().any(|s| s.id == id) {
+                id = format!("{}-{}", save_id(path), suffix);
+                suffix += 1;
+            }
+
+            // The variants share an id so that they're stored in the same
+            // place.
+            for (translated, os) in translated {
+                saves.push(SavePath::unexpanded(id.clone(), translated, os));
+            }
+        }
+
+        if saves.is_empty() {
+            continue;
+        }
+
+        let base_id = Game::id_from_title(&title);
+        if base_id.is_empty() {
+            continue;
+        }
+
+        let mut id = base_id.clone();
+        if games.iter().any(|g| g.id == id) {
+            id = match steam_app_id {
+                Some(app_id) => format!("{}{}", base_id, app_id),
+                None => base_id.clone(),
+            };
+        }
+        let mut suffix = 2;
+        while games.iter().any(|g| g.id == id) {
+            id = format!("{}{}", base_id, suffix);
+            suffix += 1;
+        }
+
+        games.push(Game {
+            title,
+            id,
+            stores: Stores {
+                steam: steam_app_id,
+                gog: entry.gog.as_ref().and_then(|g| g.id),
+            },
+            saves,
+            ..Default::default()
+        });
+    }
+
+    Ok(games)
+}
+
+/// Works out the operating systems a file applies to from its constraints,
+/// falling back to the kind of placeholder it starts with.
+fn file_os(path: &str, file: &ManifestFile) -> Vec<Os> {
+    // A constraint without an OS, e.g. only a store, doesn't narrow down the
+    // operating systems any further than the placeholder does.
+    if !file.when.is_empty() && file.when.iter().all(|w| w.os.is_some()) {
+        let mut os = Vec::new();
+        for name in file.when.iter().filter_map(|w| w.os.as_deref()) {
+            let parsed = match name {
+                "windows" => Os::Windows,
+                "linux" => Os::Linux,
+                "mac" => Os::Macos,
+                _ => continue,
+            };
+            if !os.contains(&parsed) {
+                os.push(parsed);
+            }
+        }
+        return os;
+    }
+
+    if path.starts_with("<win") {
+        vec![Os::Windows]
+    } else if path.starts_with("<xdg") {
+        vec![Os::Linux]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Splits a file which applies to Windows and other operating systems in to
+/// one for Windows and one for the others when it uses placeholders which
+/// are resolved differently on Windows, e.g. `<home>`.
+fn os_variants(path: &str, os: Vec<Os>) -> Vec<Vec<Os>> {
+    let differs = path.contains("<home>") || path.contains("<osUserName>");
+    if !differs || os == [Os::Windows] || !(os.is_empty() || os.contains(&Os::Windows)) {
+        return vec![os];
+    }
+
+    let others = if os.is_empty() {
+        vec![Os::Linux, Os::Macos]
+    } else {
+        os.into_iter().filter(|os| *os != Os::Windows).collect()
+    };
+    vec![vec![Os::Windows], others]
+}
+
+/// Replaces Ludusavi's placeholders with saveli's variables, or returns None
+/// if the path uses placeholders saveli can't resolve.
+fn translate(
+    path: &str,
+    os: &[Os],
+    steam_app_id: Option<u32>,
+    install_dir: Option<&str>,
+) -> Option<String> {
+    let windows_only = os == [Os::Windows];
+    let mac_only = os == [Os::Macos];
+    let app_id = steam_app_id.map(|id| id.to_string());
+
+    let mut translated = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(start) = rest.find('<') {
+        let end = start + rest[start..].find('>')?;
+        translated.push_str(&rest[..start]);
+
+        let value = match &rest[start + 1..end] {
+            "winAppData" => "${APPDATA}".to_owned(),
+            "winLocalAppData" => "${LOCALAPPDATA}".to_owned(),
+            "winLocalAppDataLow" => "${USERPROFILE}/AppData/LocalLow".to_owned(),
+            "winDocuments" => "${DOCUMENTS}".to_owned(),
+            "winPublic" => "${PUBLIC}".to_owned(),
+            "winProgramData" => "${PROGRAMDATA}".to_owned(),
+            "winDir" => "${WINDIR}".to_owned(),
+            "home" if windows_only => "${USERPROFILE}".to_owned(),
+            "home" => "${HOME}".to_owned(),
+            "xdgData" if mac_only => "${HOME}/Library/Application Support".to_owned(),
+            "xdgData" => "${HOME}/.local/share".to_owned(),
+            "xdgConfig" if mac_only => "${HOME}/Library/Preferences".to_owned(),
+            "xdgConfig" => "${HOME}/.config".to_owned(),
+            "osUserName" if windows_only => "${USERNAME}".to_owned(),
+            "osUserName" => "${USER}".to_owned(),
+            "storeUserId" => "*".to_owned(),
+            "storeGameId" => app_id.clone().unwrap_or_else(|| "*".to_owned()),
+            "root" if app_id.is_some() => "${STEAM_LIBRARY}".to_owned(),
+            "game" => install_dir?.to_owned(),
+            "base" => format!("${{STEAM_LIBRARY}}/steamapps/common/{}", install_dir?),
+            _ => return None,
+        };
+
+        translated.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+
+    translated.push_str(rest);
+    Some(translated)
+}
+
+/// Derives a save id from the untranslated path, so that it's the same each
+/// time the manifest is imported.
+fn save_id(path: &str) -> String {
+    let mut id = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c.is_ascii_alphanumeric() {
+            id.push(c.to_ascii_lowercase());
+        } else if !id.is_empty() && !id.ends_with('-') {
+            id.push('-');
+        }
+    }
+
+    id.trim_end_matches('-').to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+Dark Souls III:
+  files:
+    <winAppData>/DarkSoulsIII:
+      tags:
+        - save
+      when:
+        - os: windows
+    <winAppData>/DarkSoulsIII/config.ini:
+      tags:
+        - config
+  installDir:
+    DARK SOULS III: {}
+  steam:
+    id: 374320
+Stardew Valley:
+  gog:
+    id: 1453375253
+  files:
+    <xdgConfig>/StardewValley/Saves:
+      tags:
+        - save
+    <home>/Saved Games/Stardew:
+      when:
+        - os: windows
+    <base>/Saves: {}
+    <storeUserId>/remote:
+      when:
+        - store: steam
+    <regHkcu>/Software/Stardew: {}
+Registry Only:
+  registry:
+    HKEY_CURRENT_USER/Software/Game: {}
+"#;
+
+    #[test]
+    fn test_parse() {
+        let games = parse(MANIFEST).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let ds3 = &games[0];
+        assert_eq!(ds3.id, "darksoulsiii");
+        assert_eq!(ds3.stores.steam, Some(374_320));
+        assert!(!ds3.custom);
+        assert_eq!(ds3.saves.len(), 1);
+        assert_eq!(ds3.saves[0].id, "winappdata-darksoulsiii");
+        assert_eq!(ds3.saves[0].os, vec![Os::Windows]);
+
+        // <base> can't be resolved without a Steam app id, and registry keys
+        // aren't supported.
+        let stardew = &games[1];
+        assert_eq!(stardew.id, "stardewvalley");
+        assert_eq!(stardew.stores.gog, Some(1_453_375_253));
+        let ids: Vec<_> = stardew.saves.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "home-saved-games-stardew",
+                "storeuserid-remote",
+                "xdgconfig-stardewvalley-saves"
+            ]
+        );
+        assert_eq!(stardew.saves[0].os, vec![Os::Windows]);
+        assert!(stardew.saves[1].os.is_empty());
+        assert_eq!(stardew.saves[2].os, vec![Os::Linux]);
+    }
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(
+            translate("<home>/Saved Games/X", &[Os::Windows], None, None).unwrap(),
+            "${USERPROFILE}/Saved Games/X"
+        );
+        assert_eq!(
+            translate("<home>/.x", &[], None, None).unwrap(),
+            "${HOME}/.x"
+        );
+        assert_eq!(
+            translate("<xdgData>/X", &[Os::Macos], None, None).unwrap(),
+            "${HOME}/Library/Application Support/X"
+        );
+        assert_eq!(
+            translate("<base>/<storeUserId>/*.sav", &[], Some(1), Some("X")).unwrap(),
+            "${STEAM_LIBRARY}/steamapps/common/X/*/*.sav"
+        );
+        assert!(translate("<base>/Saves", &[], None, None).is_none());
+        assert!(translate("<unknown>/Saves", &[], None, None).is_none());
+    }
+
+    #[test]
+    fn test_home_without_os() {
+        let games = parse("Game:\n  files:\n    <home>/Saves/Game: {}\n").unwrap();
+        let saves = &games[0].saves;
+        assert_eq!(saves.len(), 2);
+        assert_eq!(saves[0].id, saves[1].id);
+        assert_eq!(saves[0].os, vec![Os::Windows]);
+        assert_eq!(saves[0].path(), "${USERPROFILE}/Saves/Game");
+        assert_eq!(saves[1].os, vec![Os::Linux, Os::Macos]);
+        assert_eq!(saves[1].path(), "${HOME}/Saves/Game");
+
+        assert_eq!(
+            os_variants("<home>/x", vec![Os::Windows, Os::Linux]),
+            vec![vec![Os::Windows], vec![Os::Linux]]
+        );
+        assert_eq!(
+            os_variants("<winAppData>/x", Vec::new()),
+            vec![Vec::<Os>::new()]
+        );
+    }
+
+    #[test]
+    fn test_store_without_os() {
+        let manifest = "Game:
+  files:
+    <winAppData>/Game:
+      when:
+        - store: steam
+    <xdgData>/Game:
+      when:
+        - os: linux
+        - store: gog
+";
+        let saves = &parse(manifest).unwrap()[0].saves;
+        assert_eq!(saves.len(), 2);
+        assert_eq!(saves[0].os, vec![Os::Windows]);
+        assert_eq!(saves[1].os, vec![Os::Linux]);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_home_without_os_in_prefix() {
+        let prefix = tempfile::tempdir().unwrap().into_path();
+        let user = prefix.join("drive_c/users/player");
+        std::fs::create_dir_all(user.join("Saved Games/SaveliTestGame")).unwrap();
+
+        let manifest = "Game:\n  files:\n    <home>/Saved Games/SaveliTestGame: {}\n";
+        let mut game = parse(manifest).unwrap().remove(0);
+        game.update_paths(None, &[prefix]);
+        assert_eq!(game.saves.len(), 1);
+        assert_eq!(
+            game.saves[0].expanded,
+            user.join("Saved Games/SaveliTestGame")
+        );
+    }
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(
+            save_id("<winAppData>/Dark Souls III/"),
+            "winappdata-dark-souls-iii"
+        );
+        assert_eq!(
+            Game::id_from_title("Dark Souls III: The Fire Fades™"),
+            "darksoulsiiithefirefades"
+        );
+    }
+}
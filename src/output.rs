@@ -0,0 +1,461 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use saveli::events::Event;
+use saveli::game::{Listing, SaveKind, SaveState, SaveStatus};
+use saveli::{Game, Report, Result, Settings};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// The bar for the move which is in progress, if any.
+static PROGRESS: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// Like `println!`, but quiet when printing JSON.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_json() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Switches from text meant for people to JSON meant for other programs.
+/// Commands then print a single line of JSON with their results, or a line
+/// for each event during long operations such as linking.
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Prints the value as a line of JSON when printing JSON.
+pub fn emit<T: Serialize>(value: &T) {
+    if !is_json() {
+        return;
+    }
+
+    match serde_json::to_string(value) {
+        Ok(line) => println!("{}", line),
+        Err(e) => eprintln!("Failed to serialize the output: {}", e),
+    }
+}
+
+/// Shows a progress bar for a save being copied to another filesystem. The
+/// bar is only drawn when standard error is a terminal.
+pub fn show_progress(event: &Event) {
+    let (copied_bytes, total_bytes, copied_files, total_files) = match *event {
+        Event::Progress {
+            copied_bytes,
+            total_bytes,
+            copied_files,
+            total_files,
+            ..
+        } => (copied_bytes, total_bytes, copied_files, total_files),
+        _ => return,
+    };
+
+    let mut progress = match PROGRESS.lock() {
+        Ok(progress) => progress,
+        Err(_) => return,
+    };
+
+    // A move which failed part way through leaves its bar behind.
+    if copied_bytes == 0 && copied_files == 0 {
+        if let Some(stale) = progress.take() {
+            stale.finish_and_clear();
+        }
+    }
+
+    let bar = progress.get_or_insert_with(|| {
+        let bar = ProgressBar::new(total_bytes);
+        bar.set_style(
+            ProgressStyle::with_template(
+                "[{bar:40}] {bytes}/{total_bytes}, {msg} ({eta} remaining)",
+            )
+            .expect("The progress bar template is valid")
+            .progress_chars("=> "),
+        );
+        bar
+    });
+    bar.set_position(copied_bytes);
+    bar.set_message(format!("{}/{} files", copied_files, total_files));
+
+    if copied_bytes >= total_bytes && copied_files >= total_files {
+        bar.finish_and_clear();
+        *progress = None;
+    }
+}
+
+/// The command finished, with details of what it did.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename = "done")]
+pub struct Done<'a> {
+    pub command: &'a str,
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    pub result: serde_json::Value,
+}
+
+/// Prints a table of the saves' states, followed by totals for each state.
+pub fn print_status(statuses: &[SaveStatus], settings: &Settings) {
+    if is_json() {
+        let saves: Vec<serde_json::Value> = statuses
+            .iter()
+            .map(|s| {
+                let target = match &s.state {
+                    SaveState::LinkedElsewhere(target) | SaveState::BrokenLink(target) => {
+                        Some(target)
+                    }
+                    _ => None,
+                };
+                serde_json::json!({
+                    "game": s.game.id,
+                    "title": s.game.title,
+                    "ignored": settings.game_is_ignored(&s.game.id),
+                    "save": s.location.id,
+                    "state": s.state.label(),
+                    "path": s.location.path,
+                    "dest": s.location.dest,
+                    "target": target,
+                })
+            })
+            .collect();
+        emit(&Done {
+            command: "status",
+            result: serde_json::json!({ "saves": saves }),
+        });
+        return;
+    }
+
+    if statuses.is_empty() {
+        say!("Couldn't find any saves");
+        return;
+    }
+
+    let rows: Vec<(String, &str, &SaveState, String)> = statuses
+        .iter()
+        .map(|s| {
+            let title = if settings.game_is_ignored(&s.game.id) {
+                format!("{} (ignored)", s.game.title)
+            } else {
+                s.game.title.clone()
+            };
+            (
+                title,
+                s.location.id.as_str(),
+                &s.state,
+                s.location.path.display().to_string(),
+            )
+        })
+        .collect();
+
+    let headings = ("Game", "Save", "State", "Path");
+    let title_width = rows
+        .iter()
+        .map(|r| r.0.len())
+        .chain(std::iter::once(headings.0.len()))
+        .max()
+        .unwrap_or_default();
+    let save_width = rows
+        .iter()
+        .map(|r| r.1.len())
+        .chain(std::iter::once(headings.1.len()))
+        .max()
+        .unwrap_or_default();
+    let state_width = rows
+        .iter()
+        .map(|r| r.2.label().len())
+        .chain(std::iter::once(headings.2.len()))
+        .max()
+        .unwrap_or_default();
+
+    say!(
+        "{:tw$}  {:sw$}  {:stw$}  {}",
+        headings.0,
+        headings.1,
+        headings.2,
+        headings.3,
+        tw = title_width,
+        sw = save_width,
+        stw = state_width
+    );
+
+    for (title, save_id, state, path) in &rows {
+        let path = match state {
+            SaveState::LinkedElsewhere(target) | SaveState::BrokenLink(target) => {
+                format!("{} -> {}", path, target.display())
+            }
+            _ => path.to_owned(),
+        };
+        say!(
+            "{:tw$}  {:sw$}  {:stw$}  {}",
+            title,
+            save_id,
+            state.label(),
+            path,
+            tw = title_width,
+            sw = save_width,
+            stw = state_width
+        );
+    }
+
+    say!();
+    let mut totals: Vec<(&str, usize)> = Vec::new();
+    for (_, _, state, _) in &rows {
+        match totals.iter_mut().find(|(label, _)| *label == state.label()) {
+            Some((_, count)) => *count += 1,
+            None => totals.push((state.label(), 1)),
+        }
+    }
+    totals.sort();
+    for (label, count) in totals {
+        say!("{}: {}", label, count);
+    }
+}
+
+/// Prints each game with markers for whether it's present, linked, custom
+/// or ignored.
+pub fn print_list(listings: &[Listing]) {
+    if is_json() {
+        let games: Vec<serde_json::Value> = listings
+            .iter()
+            .map(|l| {
+                serde_json::json!({
+                    "id": l.game.id,
+                    "title": l.game.title,
+                    "present": l.present,
+                    "linked": l.linked,
+                    "custom": l.game.custom,
+                    "ignored": l.ignored,
+                })
+            })
+            .collect();
+        emit(&Done {
+            command: "list",
+            result: serde_json::json!({ "games": games }),
+        });
+        return;
+    }
+
+    for listing in listings {
+        let game = listing.game;
+        let markers: Vec<&str> = [
+            (listing.present, "present"),
+            (listing.linked, "linked"),
+            (game.custom, "custom"),
+            (listing.ignored, "ignored"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, marker)| *marker)
+        .collect();
+
+        if markers.is_empty() {
+            say!("{} ({})", game.title, game.id);
+        } else {
+            say!("{} ({}) [{}]", game.title, game.id, markers.join(", "));
+        }
+    }
+
+    if listings.is_empty() {
+        say!("Couldn't find any matching games");
+    } else {
+        say!("\n{} games", listings.len());
+    }
+}
+
+/// Prints everything known about the game and the state of its saves.
+pub fn print_info(game: &Game, settings: &Settings) -> Result<()> {
+    let ignored = settings.game_is_ignored(&game.id);
+    let saves = game.saves_info(&settings.storage_path)?;
+
+    if is_json() {
+        let saves: Vec<serde_json::Value> = saves
+            .iter()
+            .map(|info| {
+                let locations: Vec<serde_json::Value> =
+                    info.locations
+                        .iter()
+                        .map(|l| {
+                            let target = match &l.state {
+                                SaveState::LinkedElsewhere(target)
+                                | SaveState::BrokenLink(target) => Some(target),
+                                _ => None,
+                            };
+                            serde_json::json!({
+                                "path": l.location.path,
+                                "state": l.state.label(),
+                                "target": target,
+                                "size": l.size,
+                                "dest": l.location.dest,
+                            })
+                        })
+                        .collect();
+                serde_json::json!({
+                    "id": info.save.id,
+                    "path": info.save.path(),
+                    "expanded": info.save.expanded,
+                    "os": info.save.os,
+                    "kind": info.save.kind,
+                    "locations": locations,
+                })
+            })
+            .collect();
+        emit(&Done {
+            command: "info",
+            result: serde_json::json!({
+                "id": game.id,
+                "title": game.title,
+                "custom": game.custom,
+                "ignored": ignored,
+                "aliases": game.aliases,
+                "stores": game.stores,
+                "notes": game.notes,
+                "saves": saves,
+            }),
+        });
+        return Ok(());
+    }
+
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+
+    say!("{} ({})", game.title, game.id);
+    say!("Custom: {}", yes_no(game.custom));
+    say!("Ignored: {}", yes_no(ignored));
+    if !game.aliases.is_empty() {
+        say!("Aliases: {}", game.aliases.join(", "));
+    }
+    if let Some(id) = game.stores.steam {
+        say!("Steam app id: {}", id);
+    }
+    if let Some(id) = game.stores.gog {
+        say!("GOG id: {}", id);
+    }
+    if let Some(notes) = &game.notes {
+        say!("Notes: {}", notes);
+    }
+
+    for info in &saves {
+        let save = info.save;
+        say!();
+        say!("Save: {}", save.id);
+        say!("  Path: {}", save.path());
+        say!("  Expanded: {}", save.expanded.display());
+        if !save.os.is_empty() {
+            let os: Vec<&str> = save.os.iter().map(|os| os.name()).collect();
+            say!("  Operating systems: {}", os.join(", "));
+        }
+        match save.kind {
+            Some(SaveKind::File) => say!("  Kind: file"),
+            Some(SaveKind::Directory) => say!("  Kind: directory"),
+            None => (),
+        }
+
+        if info.locations.is_empty() {
+            say!("  Couldn't find anything matching the path");
+        }
+
+        for l in &info.locations {
+            say!("  Location: {}", l.location.path.display());
+            match &l.state {
+                SaveState::LinkedElsewhere(target) | SaveState::BrokenLink(target) => {
+                    say!("    State: {} ({})", l.state.label(), target.display())
+                }
+                _ => say!("    State: {}", l.state.label()),
+            }
+
+            if let Some(size) = l.size {
+                say!("    Size: {}", format_size(size));
+            }
+
+            say!("    Storage location: {}", l.location.dest.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints how many games succeeded, were ignored, had nothing to do or failed,
+/// and why each failure happened.
+pub fn print_summary(report: &Report, command: &str) {
+    if is_json() {
+        let failed: Vec<serde_json::Value> = report
+            .failed
+            .iter()
+            .map(|(id, e)| {
+                serde_json::json!({
+                    "game": id,
+                    "kind": e.kind().name(),
+                    "message": e.to_string(),
+                })
+            })
+            .collect();
+        emit(&Done {
+            command,
+            result: serde_json::json!({
+                "succeeded": report.succeeded,
+                "ignored": report.ignored,
+                "nothing_to_do": report.nothing_to_do,
+                "failed": failed,
+            }),
+        });
+        return;
+    }
+
+    say!();
+    say!(
+        "{} succeeded, {} ignored, {} with nothing to do, {} failed",
+        report.succeeded.len(),
+        report.ignored.len(),
+        report.nothing_to_do.len(),
+        report.failed.len()
+    );
+
+    for (id, error) in &report.failed {
+        say!("  {}: {}", id, error);
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_done_json() {
+        let done = Done {
+            command: "recover",
+            result: serde_json::Value::Null,
+        };
+        assert_eq!(
+            serde_json::to_value(done).unwrap(),
+            json!({ "event": "done", "command": "recover" })
+        );
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+    }
+}
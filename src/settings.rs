@@ -2,7 +2,7 @@ use crate::errors::*;
 use crate::game::Game;
 use app_dirs::{AppDataType, AppInfo};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const APP_INFO: AppInfo = AppInfo {
     name: env!("CARGO_PKG_NAME"),
@@ -16,6 +16,9 @@ pub struct Settings {
     pub dry_run: bool,
     #[serde(default)]
     ignored: Vec<String>,
+    /// Wine prefixes to look for Windows saves in on other operating systems.
+    #[serde(default)]
+    pub wine_prefixes: Vec<PathBuf>,
 }
 
 impl Settings {
@@ -26,7 +29,7 @@ impl Settings {
 
     pub fn save(&self) -> Result<()> {
         let path = Settings::get_settings_path()?;
-        println!("Saving settings to {}", path.display());
+        debug!("Saving settings to {}", path.display());
         let file = std::fs::File::create(&path)?;
         Ok(serde_json::to_writer_pretty(&file, self)?)
     }
@@ -38,17 +41,45 @@ impl Settings {
     }
 
     pub fn ignore_game(&mut self, game: &Game) -> Result<()> {
-        println!("Ignoring {}", game.title);
+        info!("Ignoring {}", game.title);
         self.ignored.push(game.id.clone());
         self.save()
     }
 
     pub fn heed_game(&mut self, game: &Game) -> Result<()> {
-        println!("Heeding {}", game.title);
+        info!("Heeding {}", game.title);
         self.ignored.retain(|id| *id != game.id);
         self.save()
     }
 
+    /// Keeps a game ignored after its id has changed.
+    pub fn rename_ignored_game(&mut self, id: &str, new_id: &str) -> Result<()> {
+        if !self.game_is_ignored(id) {
+            return Ok(());
+        }
+
+        self.ignored.retain(|ignored| ignored != id);
+        self.ignored.push(new_id.to_owned());
+        self.save()
+    }
+
+    pub fn add_wine_prefix(&mut self, path: PathBuf) -> Result<()> {
+        if self.wine_prefixes.contains(&path) {
+            warn!("{} has already been added", path.display());
+            return Ok(());
+        }
+
+        info!("Adding Wine prefix {}", path.display());
+        self.wine_prefixes.push(path);
+        self.save()
+    }
+
+    pub fn remove_wine_prefix(&mut self, path: &Path) -> Result<()> {
+        info!("Removing Wine prefix {}", path.display());
+        self.wine_prefixes.retain(|p| p != path);
+        self.save()
+    }
+
     pub fn game_is_ignored(&self, id: &str) -> bool {
         // Vec::contains can't accept a &str to find a String
         self.ignored.iter().any(|ignored| ignored == id)
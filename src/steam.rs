@@ -0,0 +1,390 @@
+use crate::errors::*;
+use std::path::{Path, PathBuf};
+
+/// The difference between a 64 bit Steam ID and the 32 bit account ID used to
+/// name directories in userdata.
+const STEAM_ID_OFFSET: u64 = 76_561_197_960_265_728;
+
+/// A value in Valve's KeyValues (VDF) text format.
+#[derive(Debug, PartialEq)]
+enum Vdf {
+    String(String),
+    Object(Vec<(String, Vdf)>),
+}
+
+impl Vdf {
+    fn get(&self, key: &str) -> Option<&Vdf> {
+        match self {
+            Vdf::Object(entries) => entries
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v),
+            Vdf::String(_) => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Vdf::String(s) => Some(s),
+            Vdf::Object(_) => None,
+        }
+    }
+
+    fn entries(&self) -> &[(String, Vdf)] {
+        match self {
+            Vdf::Object(entries) => entries,
+            Vdf::String(_) => &[],
+        }
+    }
+
+    /// Parses a document, returning its root object.
+    fn parse(data: &str) -> Result<Vdf> {
+        let mut tokens = Tokens {
+            chars: data.chars().peekable(),
+        };
+        Vdf::parse_entries(&mut tokens, false)
+    }
+
+    fn parse_entries(tokens: &mut Tokens, nested: bool) -> Result<Vdf> {
+        let mut entries = Vec::new();
+        loop {
+            let key = match tokens.next()? {
+                Some(Token::String(key)) => key,
+                Some(Token::Close) if nested => break,
+                None if !nested => break,
+                _ => bail!("Unexpected token in VDF data"),
+            };
+
+            let value = match tokens.next()? {
+                Some(Token::String(value)) => Vdf::String(value),
+                Some(Token::Open) => Vdf::parse_entries(tokens, true)?,
+                _ => bail!("Missing value for {} in VDF data", key),
+            };
+
+            entries.push((key, value));
+        }
+
+        Ok(Vdf::Object(entries))
+    }
+}
+
+enum Token {
+    String(String),
+    Open,
+    Close,
+}
+
+struct Tokens<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Result<Option<Token>> {
+        while let Some(c) = self.chars.next() {
+            match c {
+                '{' => return Ok(Some(Token::Open)),
+                '}' => return Ok(Some(Token::Close)),
+                '"' => return Ok(Some(Token::String(self.quoted()?))),
+                '/' if self.chars.peek() == Some(&'/') => {
+                    self.chars.by_ref().find(|&c| c == '\n');
+                }
+                c if c.is_whitespace() => (),
+                // Conditionals such as [$WIN32] aren't needed for the files
+                // saveli reads.
+                '[' => {
+                    self.chars.by_ref().find(|&c| c == ']');
+                }
+                c => {
+                    let mut s = c.to_string();
+                    while let Some(&c) = self.chars.peek() {
+                        if c.is_whitespace() || c == '{' || c == '}' || c == '"' {
+                            break;
+                        }
+                        s.push(c);
+                        self.chars.next();
+                    }
+                    return Ok(Some(Token::String(s)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn quoted(&mut self) -> Result<String> {
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some(c) => s.push(c),
+                    None => break,
+                },
+                Some(c) => s.push(c),
+                None => break,
+            }
+        }
+
+        bail!("Unterminated string in VDF data")
+    }
+}
+
+/// A Steam installation, used to resolve the Steam path variables.
+#[derive(Debug, Default)]
+pub struct Steam {
+    root: PathBuf,
+    libraries: Vec<PathBuf>,
+    account_id: Option<u64>,
+}
+
+impl Steam {
+    /// Finds Steam in its default locations.
+    pub fn locate() -> Option<Steam> {
+        Steam::default_roots()
+            .into_iter()
+            .filter(|root| root.join("steamapps").is_dir())
+            .find_map(|root| Steam::load(&root).ok())
+    }
+
+    #[cfg(windows)]
+    fn default_roots() -> Vec<PathBuf> {
+        ["ProgramFiles(x86)", "ProgramFiles"]
+            .iter()
+            .filter_map(std::env::var_os)
+            .map(|p| PathBuf::from(p).join("Steam"))
+            .collect()
+    }
+
+    #[cfg(target_os = "macos")]
+    fn default_roots() -> Vec<PathBuf> {
+        std::env::var_os("HOME")
+            .map(|home| vec![PathBuf::from(home).join("Library/Application Support/Steam")])
+            .unwrap_or_default()
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn default_roots() -> Vec<PathBuf> {
+        let home = match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home),
+            None => return Vec::new(),
+        };
+
+        vec![
+            home.join(".steam/steam"),
+            home.join(".local/share/Steam"),
+            home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+        ]
+    }
+
+    /// Reads the library folders and most recent user of the Steam
+    /// installation at `root`.
+    pub fn load(root: &Path) -> Result<Steam> {
+        let mut steam = Steam {
+            root: root.to_path_buf(),
+            libraries: vec![root.to_path_buf()],
+            account_id: None,
+        };
+
+        let library_folders = root.join("steamapps").join("libraryfolders.vdf");
+        if library_folders.exists() {
+            let vdf = Vdf::parse(&std::fs::read_to_string(&library_folders)?)?;
+            steam.add_libraries(&vdf);
+        }
+
+        let login_users = root.join("config").join("loginusers.vdf");
+        if login_users.exists() {
+            let vdf = Vdf::parse(&std::fs::read_to_string(&login_users)?)?;
+            steam.account_id = Steam::most_recent_account_id(&vdf);
+        }
+
+        Ok(steam)
+    }
+
+    fn add_libraries(&mut self, vdf: &Vdf) {
+        let folders = match vdf.entries().first() {
+            Some((_, folders)) => folders,
+            None => return,
+        };
+
+        for (key, folder) in folders.entries() {
+            if key.parse::<u32>().is_err() {
+                continue;
+            }
+
+            // Older versions map indices directly to paths.
+            let path = match folder {
+                Vdf::String(path) => Some(path.as_str()),
+                Vdf::Object(_) => folder.get("path").and_then(Vdf::as_str),
+            };
+
+            if let Some(path) = path {
+                let path = PathBuf::from(path);
+                if !self.libraries.contains(&path) {
+                    self.libraries.push(path);
+                }
+            }
+        }
+    }
+
+    fn most_recent_account_id(vdf: &Vdf) -> Option<u64> {
+        let users = vdf.get("users")?.entries();
+        let (steam_id, _) = users
+            .iter()
+            .find(|(_, user)| user.get("MostRecent").and_then(Vdf::as_str) == Some("1"))
+            .or_else(|| users.first())?;
+        steam_id.parse::<u64>().ok()?.checked_sub(STEAM_ID_OFFSET)
+    }
+
+    /// The library containing the game, or the main library if it isn't
+    /// installed.
+    pub fn library(&self, app_id: Option<u32>) -> &Path {
+        app_id
+            .and_then(|id| {
+                self.libraries.iter().find(|lib| {
+                    lib.join("steamapps")
+                        .join(format!("appmanifest_{}.acf", id))
+                        .exists()
+                })
+            })
+            .unwrap_or(&self.root)
+    }
+
+    /// The current user's userdata directory.
+    pub fn userdata(&self) -> Option<PathBuf> {
+        self.account_id
+            .map(|id| self.root.join("userdata").join(id.to_string()))
+    }
+
+    /// The game's Proton prefix, if it has one.
+    pub fn proton_prefix(&self, app_id: u32) -> Option<PathBuf> {
+        self.libraries
+            .iter()
+            .map(|lib| {
+                lib.join("steamapps")
+                    .join("compatdata")
+                    .join(app_id.to_string())
+                    .join("pfx")
+            })
+            .find(|pfx| pfx.is_dir())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const LIBRARY_FOLDERS: &str = r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"apps"
+		{
+			"228980"		"0"
+		}
+	}
+	"1"
+	{
+		"path"		"/mnt/games/SteamLibrary"
+	}
+}
+"#;
+
+    const OLD_LIBRARY_FOLDERS: &str = r#"
+"LibraryFolders"
+{
+	"TimeNextStatsReport"		"1565116452"
+	"ContentStatsID"		"-2542276416063939543"
+	"1"		"D:\\SteamLibrary"
+}
+"#;
+
+    const LOGIN_USERS: &str = r#"
+"users"
+{
+	"76561197960287930"
+	{
+		"AccountName"		"old"
+		"MostRecent"		"0"
+	}
+	"76561197960287931"
+	{
+		"AccountName"		"recent"
+		"MostRecent"		"1"
+	}
+}
+"#;
+
+    #[test]
+    fn test_parse_vdf() {
+        let vdf = Vdf::parse(LIBRARY_FOLDERS).unwrap();
+        let folders = vdf.get("libraryfolders").unwrap();
+        assert_eq!(
+            folders.get("1").unwrap().get("path").unwrap().as_str(),
+            Some("/mnt/games/SteamLibrary")
+        );
+    }
+
+    #[test]
+    fn test_parse_vdf_unterminated() {
+        Vdf::parse("\"a\" { \"b\" \"c").unwrap_err();
+        Vdf::parse("\"a\" { \"b\" \"c\"").unwrap_err();
+    }
+
+    #[test]
+    fn test_libraries() {
+        let mut steam = Steam::default();
+        steam.add_libraries(&Vdf::parse(LIBRARY_FOLDERS).unwrap());
+        assert_eq!(
+            steam.libraries,
+            vec![
+                PathBuf::from("/home/user/.local/share/Steam"),
+                PathBuf::from("/mnt/games/SteamLibrary")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_old_libraries() {
+        let mut steam = Steam::default();
+        steam.add_libraries(&Vdf::parse(OLD_LIBRARY_FOLDERS).unwrap());
+        assert_eq!(steam.libraries, vec![PathBuf::from("D:\\SteamLibrary")]);
+    }
+
+    #[test]
+    fn test_most_recent_account_id() {
+        let vdf = Vdf::parse(LOGIN_USERS).unwrap();
+        assert_eq!(Steam::most_recent_account_id(&vdf), Some(22203));
+    }
+
+    #[test]
+    fn test_load() {
+        let root = tempdir().unwrap();
+        let library = tempdir().unwrap();
+        let pfx = library.path().join("steamapps/compatdata/374320/pfx");
+        std::fs::create_dir_all(&pfx).unwrap();
+        std::fs::write(library.path().join("steamapps/appmanifest_374320.acf"), "").unwrap();
+        std::fs::create_dir_all(root.path().join("steamapps")).unwrap();
+        std::fs::create_dir_all(root.path().join("config")).unwrap();
+        let folders = format!(
+            "\"libraryfolders\" {{ \"0\" {{ \"path\" \"{}\" }} }}",
+            library.path().display().to_string().replace('\\', "\\\\")
+        );
+        std::fs::write(root.path().join("steamapps/libraryfolders.vdf"), folders).unwrap();
+        std::fs::write(root.path().join("config/loginusers.vdf"), LOGIN_USERS).unwrap();
+
+        let steam = Steam::load(root.path()).unwrap();
+        assert_eq!(steam.library(Some(374_320)), library.path());
+        assert_eq!(steam.library(None), root.path());
+        assert_eq!(steam.proton_prefix(374_320), Some(pfx));
+        assert_eq!(steam.proton_prefix(1), None);
+        assert_eq!(
+            steam.userdata(),
+            Some(root.path().join("userdata").join("22203"))
+        );
+    }
+}
@@ -0,0 +1,592 @@
+use crate::errors::*;
+use crate::journal::{Journal, Record};
+use crate::linker::Linker;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A change to the filesystem which can be undone.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    CreatedDir(PathBuf),
+    Moved {
+        from: PathBuf,
+        to: PathBuf,
+        /// Whether something was already at `to`, in which case reverting the
+        /// move only removes what was copied inside it.
+        #[serde(default, skip_serializing_if = "is_false")]
+        existed: bool,
+    },
+    Linked { link: PathBuf, target: PathBuf },
+    RemovedLink { link: PathBuf, target: PathBuf },
+    RemovedDir(PathBuf),
+}
+
+impl Step {
+    fn apply(&self) -> Result<()> {
+        match self {
+            Step::CreatedDir(path) => Ok(std::fs::create_dir(path)?),
+            Step::Moved { from, to, .. } => Linker::move_item(from, to).map(|_| ()),
+            Step::Linked { link, target } => Linker::symlink(link, target),
+            Step::RemovedLink { link, .. } => Linker::remove_link(link),
+            Step::RemovedDir(path) => Ok(std::fs::remove_dir(path)?),
+        }
+    }
+
+    /// Undoes the step. Steps which have already been undone are left alone,
+    /// so that a rollback which was interrupted can be repeated.
+    fn undo(&self) -> Result<()> {
+        match self {
+            Step::CreatedDir(path) => {
+                if exists(path) {
+                    std::fs::remove_dir(path)?;
+                }
+            }
+            Step::Moved { from, to, .. } => match (exists(from), exists(to)) {
+                (true, false) => (),
+                (true, true) => Linker::finish_move(to, from)?,
+                _ => {
+                    Linker::move_item(to, from)?;
+                }
+            },
+            Step::Linked { link, .. } => {
+                if Linker::link_target(link).is_some() {
+                    Linker::remove_link(link)?;
+                }
+            }
+            Step::RemovedLink { link, target } => Linker::symlink(link, target)?,
+            Step::RemovedDir(path) => {
+                if !exists(path) {
+                    std::fs::create_dir(path)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Works out whether a step which was interrupted took effect, reverting
+    /// it if it was only partially applied.
+    fn resolve_interrupted(&self) -> Result<bool> {
+        Ok(match self {
+            Step::CreatedDir(path) => exists(path),
+            Step::Moved { from, to, existed } => match (exists(from), exists(to)) {
+                (false, true) => true,
+                (true, false) => false,
+                (true, true) => resolve_interrupted_copy(from, to, *existed)?,
+                (false, false) => bail!(
+                    "Neither {} nor {} exist, the interrupted move can't be recovered",
+                    from.display(),
+                    to.display()
+                ),
+            },
+            Step::Linked { link, target } => Linker::link_target(link).as_ref() == Some(target),
+            Step::RemovedLink { link, .. } => Linker::link_target(link).is_none(),
+            Step::RemovedDir(path) => !exists(path),
+        })
+    }
+}
+
+/// Resolves a move to another filesystem which was interrupted while copying
+/// or while removing the original. The original is only removed once the copy
+/// has been verified, so a complete copy means the move just needs finishing.
+/// Otherwise the original is intact and the partial copy is removed, which
+/// unlike finishing the copy can't fail for lack of space.
+fn resolve_interrupted_copy(from: &Path, to: &Path, existed: bool) -> Result<bool> {
+    let copy = match from.file_name() {
+        Some(name) if existed && from.is_dir() => to.join(name),
+        _ => to.to_path_buf(),
+    };
+    if !exists(&copy) || (existed && copy == to) {
+        return Ok(false);
+    }
+
+    if Linker::is_copy(&copy, from)? {
+        Linker::remove_item(from)?;
+        return Ok(true);
+    }
+
+    warn!("Removing the partial copy at {}", copy.display());
+    Linker::remove_item(&copy)?;
+    Ok(false)
+}
+
+fn exists(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok()
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// Records each change made while processing a game so that they can be
+/// undone in reverse order if a later change fails, leaving the game either
+/// fully processed or untouched. When a journal is used, each change is
+/// written to it before being attempted so that `recover` can undo them after
+/// a crash.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    /// Completed steps which haven't been undone, along with the order in
+    /// which they completed.
+    steps: Vec<(usize, Step)>,
+    completed: usize,
+    journal: Option<Journal>,
+    /// Why a step which failed part way through couldn't be reverted. Rolling
+    /// back then fails so that the journal is kept for `recover`.
+    unresolved: Option<Error>,
+}
+
+impl Transaction {
+    /// Runs `f` with a new transaction, rolling back any changes it made if it
+    /// fails.
+    pub fn run<F>(journal: Option<Journal>, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Transaction) -> Result<()>,
+    {
+        let mut tx = Transaction {
+            journal,
+            ..Default::default()
+        };
+
+        let err = match f(&mut tx) {
+            Ok(()) => return tx.finish(),
+            Err(e) => e,
+        };
+
+        if !tx.steps.is_empty() {
+            warn!("Rolling back {} completed steps", tx.steps.len());
+        }
+
+        if let Err(rollback_err) = tx.rollback() {
+            bail!(ErrorKind::RollbackFailed(
+                err.to_string(),
+                rollback_err.to_string()
+            ));
+        }
+
+        tx.finish()?;
+        Err(err)
+    }
+
+    /// Undoes the changes described by an unfinished journal in the storage
+    /// path, if there is one. Returns whether there was anything to recover.
+    pub fn recover(storage_path: &Path) -> Result<bool> {
+        let (journal, records) = match Journal::open(storage_path)? {
+            Some(opened) => opened,
+            None => return Ok(false),
+        };
+
+        let mut tx = Transaction::default();
+        let mut pending = None;
+        for record in records {
+            match record {
+                Record::Begin { game } => {
+                    warn!("Recovering an interrupted operation on {}", game)
+                }
+                Record::Intent(step) => {
+                    if let Some(interrupted) = pending.replace(step) {
+                        bail!(ErrorKind::CorruptJournal(format!(
+                            "{:?} wasn't completed",
+                            interrupted
+                        )));
+                    }
+                }
+                Record::Done => match pending.take() {
+                    Some(step) => tx.push(step),
+                    None => bail!(ErrorKind::CorruptJournal(
+                        "found a completion without an intent".to_owned()
+                    )),
+                },
+                Record::Undone(index) => {
+                    let before = tx.steps.len();
+                    tx.steps.retain(|(i, _)| *i != index);
+                    if tx.steps.len() == before {
+                        bail!(ErrorKind::CorruptJournal(format!(
+                            "step {} was undone but never completed",
+                            index
+                        )));
+                    }
+                }
+            }
+        }
+
+        tx.journal = Some(journal);
+        if let Some(step) = pending {
+            if step.resolve_interrupted()? {
+                tx.record(&Record::Done)?;
+                tx.push(step);
+            }
+        }
+
+        warn!("Rolling back {} completed steps", tx.steps.len());
+        tx.rollback()?;
+        tx.finish()?;
+        Ok(true)
+    }
+
+    /// Creates `path` and any missing parents, recording each directory which
+    /// didn't already exist.
+    pub fn create_dir_all(&mut self, path: &Path) -> Result<()> {
+        let missing: Vec<PathBuf> = path
+            .ancestors()
+            .take_while(|p| !exists(p))
+            .map(Path::to_path_buf)
+            .collect();
+
+        // Shallowest first so that rolling back removes the deepest first.
+        for dir in missing.into_iter().rev() {
+            self.apply(Step::CreatedDir(dir))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn move_item(&mut self, from: &Path, to: &Path) -> Result<()> {
+        self.apply(Step::Moved {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            existed: exists(to),
+        })
+    }
+
+    /// See `Linker::symlink`. Links which already existed aren't recorded.
+    pub fn symlink(&mut self, from: &Path, to: &Path) -> Result<()> {
+        if Linker::link_target(from).as_deref() == Some(to) {
+            return Ok(());
+        }
+
+        self.apply(Step::Linked {
+            link: from.to_path_buf(),
+            target: to.to_path_buf(),
+        })
+    }
+
+    pub fn remove_link(&mut self, link: &Path) -> Result<()> {
+        let target = match Linker::link_target(link) {
+            Some(target) => target,
+            None => bail!("{} isn't a link", link.display()),
+        };
+        self.apply(Step::RemovedLink {
+            link: link.to_path_buf(),
+            target,
+        })
+    }
+
+    /// Removes the empty directory at `path`.
+    pub fn remove_dir(&mut self, path: &Path) -> Result<()> {
+        self.apply(Step::RemovedDir(path.to_path_buf()))
+    }
+
+    fn apply(&mut self, step: Step) -> Result<()> {
+        self.record(&Record::Intent(step.clone()))?;
+        if let Err(e) = step.apply() {
+            // Copying to another filesystem can fail after partly taking
+            // effect, so a failed step is resolved like an interrupted one.
+            match step.resolve_interrupted() {
+                Ok(true) => {
+                    self.record(&Record::Done)?;
+                    self.push(step);
+                }
+                Ok(false) => (),
+                Err(resolve_err) => {
+                    error!("Failed to revert {:?}: {}", step, resolve_err);
+                    self.unresolved = Some(resolve_err);
+                }
+            }
+            return Err(e);
+        }
+        self.record(&Record::Done)?;
+        self.push(step);
+        Ok(())
+    }
+
+    fn push(&mut self, step: Step) {
+        self.steps.push((self.completed, step));
+        self.completed += 1;
+    }
+
+    fn record(&mut self, record: &Record) -> Result<()> {
+        match self.journal.as_mut() {
+            Some(journal) => journal.append(record),
+            None => Ok(()),
+        }
+    }
+
+    /// Undoes every recorded step in reverse order. All steps are attempted
+    /// and the first failure is returned.
+    fn rollback(&mut self) -> Result<()> {
+        let mut result = match self.unresolved.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        };
+
+        while let Some((index, step)) = self.steps.pop() {
+            match step.undo() {
+                Ok(()) => self.record(&Record::Undone(index))?,
+                Err(e) => {
+                    error!("Failed to undo {:?}: {}", step, e);
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    fn finish(self) -> Result<()> {
+        match self.journal {
+            Some(journal) => journal.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rollback_move_and_link() {
+        let c = tempdir().unwrap();
+        let src = c.path().join("src");
+        std::fs::create_dir(&src).unwrap();
+        let dest = c.path().join("storage").join("dest");
+
+        let err = Transaction::run(None, |tx| {
+            tx.create_dir_all(dest.parent().unwrap())?;
+            tx.move_item(&src, &dest)?;
+            tx.symlink(&src, &dest)?;
+            bail!("fail");
+        })
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "fail");
+        assert!(std::fs::symlink_metadata(&src).unwrap().is_dir());
+        assert!(!c.path().join("storage").exists());
+    }
+
+    #[test]
+    fn test_rollback_remove_link_and_dir() {
+        let c = tempdir().unwrap();
+        let link = c.path().join("link");
+        let target = c.path().join("target");
+        let empty = c.path().join("empty");
+        std::fs::create_dir(&target).unwrap();
+        std::fs::create_dir(&empty).unwrap();
+        Linker::symlink(&link, &target).unwrap();
+
+        Transaction::run(None, |tx| {
+            tx.remove_link(&link)?;
+            tx.remove_dir(&empty)?;
+            bail!("fail");
+        })
+        .unwrap_err();
+
+        assert_eq!(Linker::link_target(&link).unwrap(), target);
+        assert!(empty.is_dir());
+    }
+
+    #[test]
+    fn test_existing_link_is_kept() {
+        let c = tempdir().unwrap();
+        let link = c.path().join("link");
+        let target = c.path().join("target");
+        std::fs::create_dir(&target).unwrap();
+        Linker::symlink(&link, &target).unwrap();
+
+        Transaction::run(None, |tx| {
+            tx.symlink(&link, &target)?;
+            bail!("fail");
+        })
+        .unwrap_err();
+
+        assert_eq!(Linker::link_target(&link).unwrap(), target);
+    }
+
+    #[test]
+    fn test_journal_removed_after_run() {
+        let storage_path = tempdir().unwrap();
+        let journal = Journal::create(storage_path.path(), "gameid").unwrap();
+        Transaction::run(Some(journal), |tx| {
+            tx.create_dir_all(&storage_path.path().join("gameid"))
+        })
+        .unwrap();
+        assert!(!Journal::path(storage_path.path()).exists());
+        assert!(storage_path.path().join("gameid").exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_failed_copy_rolled_back() {
+        // Copying only happens between filesystems, so this needs a temporary
+        // directory on a different one.
+        let shm = Path::new("/dev/shm");
+        let storage_path = tempdir().unwrap();
+        if !shm.is_dir() || Linker::same_filesystem(shm, storage_path.path()) {
+            return;
+        }
+        let c = tempfile::tempdir_in(shm).unwrap();
+        let src = c.path().join("src");
+        std::fs::create_dir(&src).unwrap();
+        std::fs::write(src.join("a"), "a").unwrap();
+        // Copying a dangling link fails part way through.
+        std::os::unix::fs::symlink(c.path().join("missing"), src.join("b")).unwrap();
+        let dest = storage_path.path().join("gameid").join("saveid");
+
+        let journal = Journal::create(storage_path.path(), "gameid").unwrap();
+        Transaction::run(Some(journal), |tx| {
+            tx.create_dir_all(dest.parent().unwrap())?;
+            tx.move_item(&src, &dest)
+        })
+        .unwrap_err();
+
+        assert_eq!(std::fs::read_to_string(src.join("a")).unwrap(), "a");
+        assert_eq!(std::fs::read_dir(storage_path.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_recover_nothing() {
+        let storage_path = tempdir().unwrap();
+        assert!(!Transaction::recover(storage_path.path()).unwrap());
+    }
+
+    #[test]
+    fn test_recover_completed_steps() {
+        let storage_path = tempdir().unwrap();
+        let src = storage_path.path().join("src");
+        let dest = storage_path.path().join("gameid").join("saveid");
+        std::fs::create_dir(&src).unwrap();
+
+        // Simulate a crash by forgetting the transaction.
+        let mut tx = Transaction {
+            journal: Some(Journal::create(storage_path.path(), "gameid").unwrap()),
+            ..Default::default()
+        };
+        tx.create_dir_all(dest.parent().unwrap()).unwrap();
+        tx.move_item(&src, &dest).unwrap();
+        tx.symlink(&src, &dest).unwrap();
+        drop(tx);
+
+        assert!(Transaction::recover(storage_path.path()).unwrap());
+        assert!(std::fs::symlink_metadata(&src).unwrap().is_dir());
+        assert!(!dest.parent().unwrap().exists());
+        assert!(!Journal::path(storage_path.path()).exists());
+    }
+
+    #[test]
+    fn test_recover_interrupted_move() {
+        let storage_path = tempdir().unwrap();
+        let src = storage_path.path().join("src");
+        let dest = storage_path.path().join("dest");
+        std::fs::create_dir(&src).unwrap();
+        std::fs::write(src.join("a"), "a").unwrap();
+        std::fs::write(src.join("b"), "b").unwrap();
+        // A partial copy.
+        std::fs::create_dir(&dest).unwrap();
+        std::fs::write(dest.join("a"), "a").unwrap();
+
+        let mut journal = Journal::create(storage_path.path(), "gameid").unwrap();
+        journal
+            .append(&Record::Intent(Step::Moved {
+                from: src.clone(),
+                to: dest.clone(),
+                existed: false,
+            }))
+            .unwrap();
+        drop(journal);
+
+        assert!(Transaction::recover(storage_path.path()).unwrap());
+        assert_eq!(std::fs::read_to_string(src.join("a")).unwrap(), "a");
+        assert_eq!(std::fs::read_to_string(src.join("b")).unwrap(), "b");
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn test_recover_interrupted_move_into_existing_dir() {
+        let storage_path = tempdir().unwrap();
+        let src = storage_path.path().join("src");
+        let dest = storage_path.path().join("dest");
+        std::fs::create_dir(&src).unwrap();
+        std::fs::write(src.join("a"), "a").unwrap();
+        std::fs::write(src.join("b"), "b").unwrap();
+        std::fs::create_dir(&dest).unwrap();
+        std::fs::write(dest.join("a"), "already stored").unwrap();
+        // A partial copy inside the directory which was already there.
+        std::fs::create_dir(dest.join("src")).unwrap();
+        std::fs::write(dest.join("src").join("a"), "a").unwrap();
+
+        let mut journal = Journal::create(storage_path.path(), "gameid").unwrap();
+        journal
+            .append(&Record::Intent(Step::Moved {
+                from: src.clone(),
+                to: dest.clone(),
+                existed: true,
+            }))
+            .unwrap();
+        drop(journal);
+
+        assert!(Transaction::recover(storage_path.path()).unwrap());
+        assert_eq!(std::fs::read_to_string(src.join("a")).unwrap(), "a");
+        assert_eq!(std::fs::read_to_string(src.join("b")).unwrap(), "b");
+        assert_eq!(
+            std::fs::read_to_string(dest.join("a")).unwrap(),
+            "already stored"
+        );
+        assert!(!dest.join("src").exists());
+        assert!(!Journal::path(storage_path.path()).exists());
+    }
+
+    #[test]
+    fn test_recover_interrupted_removal_after_copy() {
+        let storage_path = tempdir().unwrap();
+        let src = storage_path.path().join("src");
+        let dest = storage_path.path().join("dest");
+        // The copy was verified and the original was being removed.
+        std::fs::create_dir(&src).unwrap();
+        std::fs::write(src.join("b"), "b").unwrap();
+        std::fs::create_dir(&dest).unwrap();
+        std::fs::write(dest.join("a"), "a").unwrap();
+        std::fs::write(dest.join("b"), "b").unwrap();
+
+        let mut journal = Journal::create(storage_path.path(), "gameid").unwrap();
+        journal
+            .append(&Record::Intent(Step::Moved {
+                from: src.clone(),
+                to: dest.clone(),
+                existed: false,
+            }))
+            .unwrap();
+        drop(journal);
+
+        assert!(Transaction::recover(storage_path.path()).unwrap());
+        assert_eq!(std::fs::read_to_string(src.join("a")).unwrap(), "a");
+        assert_eq!(std::fs::read_to_string(src.join("b")).unwrap(), "b");
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn test_recover_partial_rollback() {
+        let storage_path = tempdir().unwrap();
+        let a = storage_path.path().join("a");
+        let b = storage_path.path().join("b");
+
+        let mut tx = Transaction {
+            journal: Some(Journal::create(storage_path.path(), "gameid").unwrap()),
+            ..Default::default()
+        };
+        tx.create_dir_all(&a).unwrap();
+        tx.create_dir_all(&b).unwrap();
+        // Undo the first step but not the second.
+        let (index, step) = tx.steps.remove(0);
+        step.undo().unwrap();
+        tx.record(&Record::Undone(index)).unwrap();
+        drop(tx);
+
+        assert!(Transaction::recover(storage_path.path()).unwrap());
+        assert!(!a.exists());
+        assert!(!b.exists());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::database;
+use crate::game::SavePath;
+use crate::variables;
+use serde::Serialize;
+
+/// A problem with an entry in a database file.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Finding {
+    /// A JSON pointer to the value with the problem.
+    pub pointer: String,
+    pub message: String,
+}
+
+impl Finding {
+    fn new<T: Into<String>>(pointer: String, message: T) -> Finding {
+        Finding {
+            pointer,
+            message: message.into(),
+        }
+    }
+}
+
+/// Checks a database file for problems which would otherwise only be noticed
+/// when it's loaded on someone's machine.
+pub fn validate(data: &str) -> Vec<Finding> {
+    let games = match database::parse(data) {
+        Ok(games) => games,
+        Err(e) => return vec![Finding::new(String::new(), e.to_string())],
+    };
+
+    let mut findings = Vec::new();
+
+    for (i, game) in games.iter().enumerate() {
+        let pointer = format!("/games/{}", i);
+
+        if game.title.trim().is_empty() {
+            findings.push(Finding::new(
+                format!("{}/title", pointer),
+                "The title is empty",
+            ));
+        }
+
+        check_id(&game.id, format!("{}/id", pointer), &mut findings);
+
+        // Custom games are allowed to override others.
+        let duplicate = games[..i]
+            .iter()
+            .position(|g| g.id == game.id && g.custom == game.custom);
+        if let Some(other) = duplicate {
+            findings.push(Finding::new(
+                format!("{}/id", pointer),
+                format!("The game id {} is also used by /games/{}", game.id, other),
+            ));
+        }
+
+        for (j, save) in game.saves.iter().enumerate() {
+            let pointer = format!("{}/saves/{}", pointer, j);
+
+            check_id(&save.id, format!("{}/id", pointer), &mut findings);
+
+            // Saves for different operating systems share ids so that they're
+            // stored in the same place.
+            let duplicate = game.saves[..j]
+                .iter()
+                .position(|s| s.id == save.id && shares_os(s, save));
+            if let Some(other) = duplicate {
+                findings.push(Finding::new(
+                    format!("{}/id", pointer),
+                    format!(
+                        "The save id {} is also used by /games/{}/saves/{}",
+                        save.id, i, other
+                    ),
+                ));
+            }
+
+            check_path(save.path(), format!("{}/path", pointer), &mut findings);
+
+            for (key, patterns) in [("include", &save.include), ("exclude", &save.exclude)] {
+                for (k, pattern) in patterns.iter().enumerate() {
+                    if let Err(e) = glob::Pattern::new(pattern) {
+                        findings.push(Finding::new(
+                            format!("{}/{}/{}", pointer, key, k),
+                            format!("The pattern {} is invalid: {}", pattern, e.msg),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    findings
+}
+
+/// Whether the game or save id only contains lowercase letters, digits, '-'
+/// and '_'. Ids are used as directory names in the storage path.
+pub fn is_valid_id(id: &str) -> bool {
+    id.chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+fn check_id(id: &str, pointer: String, findings: &mut Vec<Finding>) {
+    if id.is_empty() {
+        findings.push(Finding::new(pointer, "The id is empty"));
+    } else if !is_valid_id(id) {
+        findings.push(Finding::new(
+            pointer,
+            format!(
+                "The id {} must only contain lowercase letters, digits, '-' and '_'",
+                id
+            ),
+        ));
+    }
+}
+
+fn check_path(path: &str, pointer: String, findings: &mut Vec<Finding>) {
+    for name in variables::names(path) {
+        if !variables::KNOWN.contains(&name.as_str()) {
+            findings.push(Finding::new(
+                pointer.clone(),
+                format!("The variable {} isn't known", name),
+            ));
+        }
+    }
+
+    if path.starts_with('$') || path.starts_with('%') {
+        return;
+    }
+
+    let is_windows_absolute = path.get(1..3) == Some(":\\") || path.get(1..3) == Some(":/");
+    let message = if path.starts_with('/') || path.starts_with('\\') || is_windows_absolute {
+        "The path is hardcoded, it should start with a variable such as ${HOME}"
+    } else {
+        "The path is relative, it should start with a variable such as ${HOME}"
+    };
+    findings.push(Finding::new(pointer, message));
+}
+
+fn shares_os(a: &SavePath, b: &SavePath) -> bool {
+    a.os.is_empty() || b.os.is_empty() || a.os.iter().any(|os| b.os.contains(os))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pointers(findings: &[Finding]) -> Vec<&str> {
+        findings.iter().map(|f| f.pointer.as_str()).collect()
+    }
+
+    #[test]
+    fn test_validate_bundled_database() {
+        assert_eq!(validate(include_str!("../res/database.json")), vec![]);
+    }
+
+    #[test]
+    fn test_validate_invalid_json() {
+        let findings = validate("{");
+        assert_eq!(pointers(&findings), vec![""]);
+    }
+
+    #[test]
+    fn test_validate_games() {
+        let json = json!({
+            "version": 2,
+            "games": [
+                { "title": "A", "id": "a", "saves": [] },
+                { "title": " ", "id": "a", "saves": [] },
+                { "title": "Custom A", "id": "a", "custom": true, "saves": [] },
+                { "title": "B", "id": "B!", "saves": [] }
+            ]
+        });
+        let findings = validate(&json.to_string());
+        assert_eq!(
+            pointers(&findings),
+            vec!["/games/1/title", "/games/1/id", "/games/3/id"]
+        );
+        assert_eq!(
+            findings[1].message,
+            "The game id a is also used by /games/0"
+        );
+    }
+
+    #[test]
+    fn test_validate_saves() {
+        let json = json!({
+            "version": 2,
+            "games": [{
+                "title": "A",
+                "id": "a",
+                "saves": [
+                    { "id": "saves", "path": "${APPDATA}/A", "os": ["windows"] },
+                    { "id": "saves", "path": "${HOME}/.a", "os": ["linux"] },
+                    { "id": "saves", "path": "$HOME/.config/a" },
+                    { "id": "unknown", "path": "${SAVELI_UNKNOWN}/a" },
+                    { "id": "relative", "path": "a/b" },
+                    { "id": "unix", "path": "/home/a" },
+                    { "id": "windows", "path": "C:\\Users\\a" },
+                    {
+                        "id": "filtered",
+                        "path": "${HOME}/.b",
+                        "include": ["*.sav", "["],
+                        "exclude": ["backup/a**"]
+                    }
+                ]
+            }]
+        });
+        let findings = validate(&json.to_string());
+        assert_eq!(
+            pointers(&findings),
+            vec![
+                "/games/0/saves/2/id",
+                "/games/0/saves/3/path",
+                "/games/0/saves/4/path",
+                "/games/0/saves/5/path",
+                "/games/0/saves/6/path",
+                "/games/0/saves/7/include/1",
+                "/games/0/saves/7/exclude/0",
+            ]
+        );
+        assert!(findings[2].message.contains("relative"));
+        assert!(findings[3].message.contains("hardcoded"));
+        assert!(findings[4].message.contains("hardcoded"));
+    }
+}
@@ -0,0 +1,301 @@
+use crate::errors::*;
+use crate::steam::Steam;
+use std::path::{Path, PathBuf};
+
+/// The variables which can be used in the database. Other environment
+/// variables only work on some machines.
+pub const KNOWN: &[&str] = &[
+    "STEAM_LIBRARY",
+    "STEAM_USERDATA",
+    "PROTON_PREFIX",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "DOCUMENTS",
+    "PUBLIC",
+    "PROGRAMDATA",
+    "WINDIR",
+    "USERNAME",
+    "HOME",
+    "USER",
+    "XDG_CONFIG_HOME",
+    "XDG_DATA_HOME",
+];
+
+/// Variables which can be used in save paths in addition to environment
+/// variables.
+#[derive(Debug, Default)]
+pub struct Variables {
+    values: Vec<(&'static str, PathBuf)>,
+    /// Whether Windows paths are being mapped in to a prefix, in which case
+    /// their separators need converting.
+    windows_prefix: bool,
+}
+
+impl Variables {
+    /// The Steam variables for the game with `steam_app_id`.
+    /// - `STEAM_LIBRARY`: the library the game is installed in.
+    /// - `STEAM_USERDATA`: the most recent user's userdata directory.
+    /// - `PROTON_PREFIX`: the game's Proton prefix.
+    pub fn for_game(steam: Option<&Steam>, steam_app_id: Option<u32>) -> Variables {
+        let mut vars = Variables::default();
+        let steam = match steam {
+            Some(steam) => steam,
+            None => return vars,
+        };
+
+        vars.values
+            .push(("STEAM_LIBRARY", steam.library(steam_app_id).to_path_buf()));
+
+        if let Some(userdata) = steam.userdata() {
+            vars.values.push(("STEAM_USERDATA", userdata));
+        }
+
+        if let Some(pfx) = steam_app_id.and_then(|id| steam.proton_prefix(id)) {
+            vars.values.push(("PROTON_PREFIX", pfx));
+        }
+
+        vars
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Path> {
+        self.values
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_path())
+    }
+
+    /// Returns variables which map Windows paths in to the Proton prefix, if
+    /// there is one.
+    pub fn proton(&self) -> Option<Variables> {
+        let user = self
+            .get("PROTON_PREFIX")?
+            .join("drive_c")
+            .join("users")
+            .join("steamuser");
+        Some(self.with_windows_user(&user))
+    }
+
+    /// Returns variables which map Windows paths in to the Wine prefix at
+    /// `prefix`.
+    pub fn wine(&self, prefix: &Path) -> Variables {
+        let users = prefix.join("drive_c").join("users");
+        let user_name = std::env::var("USER").unwrap_or_default();
+        let user = if !user_name.is_empty() && users.join(&user_name).is_dir() {
+            users.join(user_name)
+        } else {
+            // The prefix may have been created by another user, e.g. when it's
+            // been copied from another machine.
+            std::fs::read_dir(&users)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .find(|p| p.is_dir() && !p.ends_with("Public"))
+                .unwrap_or_else(|| users.join(user_name))
+        };
+
+        self.with_windows_user(&user)
+    }
+
+    /// Returns a copy which maps the Windows user directory variables to
+    /// `user`, e.g. a user's directory within a Wine prefix.
+    fn with_windows_user(&self, user: &Path) -> Variables {
+        let mut values = self.values.clone();
+        values.extend(vec![
+            ("USERPROFILE", user.to_path_buf()),
+            ("APPDATA", user.join("AppData").join("Roaming")),
+            ("LOCALAPPDATA", user.join("AppData").join("Local")),
+            ("DOCUMENTS", user.join("Documents")),
+        ]);
+
+        if let Some(drive_c) = user.parent().and_then(Path::parent) {
+            values.extend(vec![
+                ("PUBLIC", drive_c.join("users").join("Public")),
+                ("PROGRAMDATA", drive_c.join("ProgramData")),
+            ]);
+        }
+
+        Variables {
+            values,
+            windows_prefix: true,
+        }
+    }
+
+    /// Expands the variables in `path`, falling back to environment variables.
+    /// Windows style `%NAME%` variables are supported too.
+    pub fn expand(&self, path: &str) -> Result<PathBuf> {
+        let path = windows_variables_to_shell(path);
+        let expanded = shellexpand::env_with_context(&path, |name| {
+            if let Some(value) = self.get(name) {
+                return Ok(Some(value.to_string_lossy().into_owned()));
+            }
+
+            // There's no environment variable for the documents directory.
+            #[cfg(windows)]
+            {
+                if name == "DOCUMENTS" {
+                    if let Ok(profile) = std::env::var("USERPROFILE") {
+                        return Ok(Some(format!("{}\\Documents", profile)));
+                    }
+                }
+            }
+
+            // Windows variables from this machine mustn't leak in to paths
+            // mapped in to a prefix.
+            if self.windows_prefix {
+                return Err(ErrorKind::UnknownVariable(name.to_owned()));
+            }
+
+            match std::env::var(name) {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(ErrorKind::UnknownVariable(name.to_owned())),
+            }
+        })
+        .map_err(|e| Error::from(e.cause))?;
+
+        if self.windows_prefix {
+            Ok(PathBuf::from(expanded.replace('\\', "/")))
+        } else {
+            Ok(PathBuf::from(expanded.into_owned()))
+        }
+    }
+}
+
+/// The names of the variables used in `path`.
+pub fn names(path: &str) -> Vec<String> {
+    let path = windows_variables_to_shell(path);
+    let mut names = Vec::new();
+    let mut rest = path.as_str();
+    while let Some(start) = rest.find('$') {
+        rest = &rest[start + 1..];
+        let name = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => &braced[..end],
+                None => break,
+            },
+            None => {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                &rest[..end]
+            }
+        };
+
+        if !name.is_empty() {
+            names.push(name.to_owned());
+        }
+    }
+
+    names
+}
+
+/// Converts `%NAME%` variables to `${NAME}`.
+fn windows_variables_to_shell(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(start) = rest.find('%') {
+        let after = &rest[start + 1..];
+        let end = match after.find('%') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let name = &after[..end];
+        let valid = !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if valid {
+            result.push_str(&rest[..start]);
+            result.push_str("${");
+            result.push_str(name);
+            result.push('}');
+            rest = &after[end + 1..];
+        } else {
+            result.push_str(&rest[..=start]);
+            rest = after;
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            names("${APPDATA}/$HOME/%DOCUMENTS%/100%/$"),
+            vec!["APPDATA", "HOME", "DOCUMENTS"]
+        );
+    }
+
+    #[test]
+    fn test_expand_unknown_variable() {
+        let err = Variables::default()
+            .expand("${SAVELI_UNKNOWN_VARIABLE}/a")
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UnknownVariable(_)));
+    }
+
+    #[test]
+    fn test_expand_steam_variable() {
+        let vars = Variables {
+            values: vec![("STEAM_USERDATA", PathBuf::from("/steam/userdata/1"))],
+            windows_prefix: false,
+        };
+        assert_eq!(
+            vars.expand("${STEAM_USERDATA}/374320/remote").unwrap(),
+            PathBuf::from("/steam/userdata/1/374320/remote")
+        );
+    }
+
+    #[test]
+    fn test_expand_proton() {
+        let vars = Variables {
+            values: vec![("PROTON_PREFIX", PathBuf::from("/pfx"))],
+            windows_prefix: false,
+        };
+        let proton = vars.proton().unwrap();
+        assert_eq!(
+            proton.expand("${APPDATA}\\DarkSoulsIII").unwrap(),
+            Path::new("/pfx/drive_c/users/steamuser/AppData/Roaming/DarkSoulsIII")
+        );
+        proton.expand("${SAVELI_UNKNOWN_VARIABLE}").unwrap_err();
+    }
+
+    #[test]
+    fn test_windows_variables_to_shell() {
+        assert_eq!(
+            windows_variables_to_shell("%APPDATA%\\Game"),
+            "${APPDATA}\\Game"
+        );
+        assert_eq!(
+            windows_variables_to_shell("%DOCUMENTS%/%a b%/100%"),
+            "${DOCUMENTS}/%a b%/100%"
+        );
+    }
+
+    #[test]
+    fn test_expand_wine() {
+        let prefix = tempfile::tempdir().unwrap();
+        let users = prefix.path().join("drive_c").join("users");
+        std::fs::create_dir_all(users.join("Public")).unwrap();
+        std::fs::create_dir_all(users.join("saveli-test-user")).unwrap();
+
+        let wine = Variables::default().wine(prefix.path());
+        assert_eq!(
+            wine.expand("%LOCALAPPDATA%\\Game").unwrap(),
+            users.join("saveli-test-user/AppData/Local/Game")
+        );
+        assert_eq!(wine.expand("${PUBLIC}").unwrap(), users.join("Public"));
+    }
+
+    #[test]
+    fn test_no_proton_prefix() {
+        assert!(Variables::default().proton().is_none());
+    }
+}